@@ -0,0 +1,438 @@
+use crate::shell;
+
+/// How deep the value of a variable may be evaluated as an expression of its own,
+/// which stops a variable that refers to itself from recursing forever.
+const MAX_DEPTH: usize = 32;
+
+#[derive(Debug,Clone,PartialEq)]
+enum Token {
+    Number(i64),
+    Name(String),
+    Operator(&'static str),
+}
+
+/// The operators in the order they are tried when tokenizing, so the longer ones come first.
+const OPERATORS: &[&str] = &[
+    "<<=", ">>=",
+    "**", "++", "--", "<<", ">>", "<=", ">=", "==", "!=", "&&", "||",
+    "*=", "/=", "%=", "+=", "-=", "&=", "^=", "|=",
+    "+", "-", "*", "/", "%", "<", ">", "&", "^", "|", "!", "~", "?", ":", "=", ",", "(", ")",
+];
+
+#[derive(Debug,Clone,PartialEq)]
+enum Expr {
+    Number(i64),
+    Variable(String),
+    Unary(&'static str, Box<Expr>),
+    Binary(&'static str, Box<Expr>, Box<Expr>),
+    Conditional(Box<Expr>, Box<Expr>, Box<Expr>),
+    /// An assignment with the operator that is combined with the old value, like `+` for `+=`.
+    Assign(String, Option<&'static str>, Box<Expr>),
+    /// `++` or `--` before or after a variable with the amount to add and if the new value is the result.
+    Increment(String, i64, bool),
+}
+
+/// This function evaluates an arithmetic expression the way `$(( ))` and `(( ))` do.
+/// The expression works on 64 bit integers with the operators of C, including assignments,
+/// and a name stands for the value of that shell variable, which is 0 if it is unset or empty.
+pub fn evaluate(expression: &str) -> Result<i64,String> {
+    evaluate_depth(expression, 0)
+}
+
+fn evaluate_depth(expression: &str, depth: usize) -> Result<i64,String> {
+    if depth > MAX_DEPTH {
+        return Err(format!("{}: expression recursion level exceeded", expression.trim()));
+    }
+    let tokens = tokenize(expression)?;
+    if tokens.is_empty() {
+        return Ok(0);
+    }
+    let mut parser = Parser { tokens, pos: 0, expression };
+    let expr = parser.comma()?;
+    if parser.pos < parser.tokens.len() {
+        return Err(parser.syntax_error());
+    }
+    eval(&expr, depth)
+}
+
+/// This function splits an expression into numbers, names and operators.
+/// Numbers can be decimal, octal with a leading `0`, hexadecimal with a leading `0x` or in any base
+/// from 2 to 64 written as `base#digits`.
+fn tokenize(expression: &str) -> Result<Vec<Token>,String> {
+    let mut tokens = Vec::new();
+    let mut rest = expression;
+    while let Some(chr) = rest.chars().next() {
+        if chr.is_whitespace() {
+            rest = &rest[chr.len_utf8()..];
+        }
+        else if chr.is_ascii_digit() {
+            let end = rest.find(|chr: char| !(chr.is_ascii_alphanumeric() || chr == '#' || chr == '@' || chr == '_')).unwrap_or(rest.len());
+            tokens.push(Token::Number(parse_number(&rest[..end])?));
+            rest = &rest[end..];
+        }
+        else if chr.is_ascii_alphabetic() || chr == '_' {
+            let mut end = rest.find(|chr: char| !(chr.is_ascii_alphanumeric() || chr == '_')).unwrap_or(rest.len());
+            // namespaced names such as ns::var
+            while rest[end..].starts_with("::") && rest[end + 2..].starts_with(|chr: char| chr.is_ascii_alphabetic() || chr == '_') {
+                end += 2;
+                end += rest[end..].find(|chr: char| !(chr.is_ascii_alphanumeric() || chr == '_')).unwrap_or(rest.len() - end);
+            }
+            tokens.push(Token::Name(rest[..end].to_string()));
+            rest = &rest[end..];
+        }
+        else {
+            match OPERATORS.iter().find(|operator| rest.starts_with(*operator)) {
+                Some(operator) => {
+                    tokens.push(Token::Operator(operator));
+                    rest = &rest[operator.len()..];
+                },
+                None => return Err(format!("{}: syntax error: invalid arithmetic operator (error token is \"{}\")", expression.trim(), rest)),
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+/// This function reads an integer constant.
+fn parse_number(text: &str) -> Result<i64,String> {
+    let invalid = || format!("{}: value too great for base (error token is \"{}\")", text, text);
+    if let Some((base, digits)) = text.split_once('#') {
+        let base: u32 = base.parse().map_err(|_| format!("{}: invalid arithmetic base", text))?;
+        if !(2..=64).contains(&base) || digits.is_empty() {
+            return Err(format!("{}: invalid arithmetic base", text));
+        }
+        let mut value: i64 = 0;
+        for chr in digits.chars() {
+            let digit = match chr {
+                '0'..='9' => chr as u32 - '0' as u32,
+                'a'..='z' => chr as u32 - 'a' as u32 + 10,
+                'A'..='Z' if base <= 36 => chr as u32 - 'A' as u32 + 10,
+                'A'..='Z' => chr as u32 - 'A' as u32 + 36,
+                '@' => 62,
+                '_' => 63,
+                _ => return Err(invalid()),
+            };
+            if digit >= base {
+                return Err(invalid());
+            }
+            value = value.wrapping_mul(base as i64).wrapping_add(digit as i64);
+        }
+        return Ok(value);
+    }
+
+    let (digits, radix) = if let Some(hex) = text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        (hex, 16)
+    }
+    else if text.len() > 1 && text.starts_with('0') {
+        (&text[1..], 8)
+    }
+    else {
+        (text, 10)
+    };
+    u64::from_str_radix(digits, radix).map(|value| value as i64).map_err(|_| invalid())
+}
+
+/// A recursive descent parser with one function for every level of precedence, from the lowest to the highest.
+struct Parser<'a> {
+    tokens: Vec<Token>,
+    pos: usize,
+    expression: &'a str,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    /// Consumes the next token if it is one of the given operators and returns it.
+    fn accept(&mut self, operators: &[&'static str]) -> Option<&'static str> {
+        match self.peek() {
+            Some(Token::Operator(operator)) if operators.contains(operator) => {
+                let operator = *operator;
+                self.pos += 1;
+                Some(operator)
+            },
+            _ => None,
+        }
+    }
+
+    fn expect(&mut self, operator: &'static str) -> Result<(),String> {
+        match self.accept(&[operator]) {
+            Some(_) => Ok(()),
+            None => Err(self.syntax_error()),
+        }
+    }
+
+    fn syntax_error(&self) -> String {
+        let rest: Vec<String> = self.tokens[self.pos.min(self.tokens.len())..].iter().map(|token| match token {
+            Token::Number(number) => number.to_string(),
+            Token::Name(name) => name.clone(),
+            Token::Operator(operator) => operator.to_string(),
+        }).collect();
+        if rest.is_empty() {
+            format!("{}: syntax error: operand expected", self.expression.trim())
+        }
+        else {
+            format!("{}: syntax error in expression (error token is \"{}\")", self.expression.trim(), rest.join(" "))
+        }
+    }
+
+    fn comma(&mut self) -> Result<Expr,String> {
+        let mut expr = self.assignment()?;
+        while self.accept(&[","]).is_some() {
+            expr = Expr::Binary(",", Box::new(expr), Box::new(self.assignment()?));
+        }
+        Ok(expr)
+    }
+
+    fn assignment(&mut self) -> Result<Expr,String> {
+        if let (Some(Token::Name(name)), Some(Token::Operator(operator))) = (self.tokens.get(self.pos), self.tokens.get(self.pos + 1)) {
+            let combine = match *operator {
+                "=" => Some(None),
+                "*=" => Some(Some("*")),
+                "/=" => Some(Some("/")),
+                "%=" => Some(Some("%")),
+                "+=" => Some(Some("+")),
+                "-=" => Some(Some("-")),
+                "<<=" => Some(Some("<<")),
+                ">>=" => Some(Some(">>")),
+                "&=" => Some(Some("&")),
+                "^=" => Some(Some("^")),
+                "|=" => Some(Some("|")),
+                _ => None,
+            };
+            if let Some(combine) = combine {
+                let name = name.clone();
+                self.pos += 2;
+                return Ok(Expr::Assign(name, combine, Box::new(self.assignment()?)));
+            }
+        }
+        self.conditional()
+    }
+
+    fn conditional(&mut self) -> Result<Expr,String> {
+        let condition = self.binary(0)?;
+        if self.accept(&["?"]).is_none() {
+            return Ok(condition);
+        }
+        let then = self.comma()?;
+        self.expect(":")?;
+        let otherwise = self.conditional()?;
+        Ok(Expr::Conditional(Box::new(condition), Box::new(then), Box::new(otherwise)))
+    }
+
+    /// Parses the left associative binary operators, where `level` indexes the table from the lowest precedence.
+    fn binary(&mut self, level: usize) -> Result<Expr,String> {
+        const LEVELS: [&[&str]; 10] = [
+            &["||"],
+            &["&&"],
+            &["|"],
+            &["^"],
+            &["&"],
+            &["==", "!="],
+            &["<", ">", "<=", ">="],
+            &["<<", ">>"],
+            &["+", "-"],
+            &["*", "/", "%"],
+        ];
+        if level == LEVELS.len() {
+            return self.power();
+        }
+        let mut expr = self.binary(level + 1)?;
+        while let Some(operator) = self.accept(LEVELS[level]) {
+            expr = Expr::Binary(operator, Box::new(expr), Box::new(self.binary(level + 1)?));
+        }
+        Ok(expr)
+    }
+
+    fn power(&mut self) -> Result<Expr,String> {
+        let base = self.unary()?;
+        if self.accept(&["**"]).is_some() {
+            // right associative, so 2**3**2 is 2**9
+            return Ok(Expr::Binary("**", Box::new(base), Box::new(self.power()?)));
+        }
+        Ok(base)
+    }
+
+    fn unary(&mut self) -> Result<Expr,String> {
+        if let Some(operator) = self.accept(&["++", "--"]) {
+            return match self.peek() {
+                Some(Token::Name(name)) => {
+                    let name = name.clone();
+                    self.pos += 1;
+                    Ok(Expr::Increment(name, if operator == "++" { 1 } else { -1 }, true))
+                },
+                // without a variable after them they are two signs, as in --5
+                _ => {
+                    let sign = if operator == "++" { "+" } else { "-" };
+                    Ok(Expr::Unary(sign, Box::new(Expr::Unary(sign, Box::new(self.unary()?)))))
+                },
+            };
+        }
+        if let Some(operator) = self.accept(&["-", "+", "!", "~"]) {
+            return Ok(Expr::Unary(operator, Box::new(self.unary()?)));
+        }
+        self.postfix()
+    }
+
+    fn postfix(&mut self) -> Result<Expr,String> {
+        let expr = self.primary()?;
+        if let Expr::Variable(name) = &expr {
+            if let Some(operator) = self.accept(&["++", "--"]) {
+                return Ok(Expr::Increment(name.clone(), if operator == "++" { 1 } else { -1 }, false));
+            }
+        }
+        Ok(expr)
+    }
+
+    fn primary(&mut self) -> Result<Expr,String> {
+        match self.peek().cloned() {
+            Some(Token::Number(number)) => {
+                self.pos += 1;
+                Ok(Expr::Number(number))
+            },
+            Some(Token::Name(name)) => {
+                self.pos += 1;
+                Ok(Expr::Variable(name))
+            },
+            Some(Token::Operator("(")) => {
+                self.pos += 1;
+                let expr = self.comma()?;
+                self.expect(")")?;
+                Ok(expr)
+            },
+            _ => Err(self.syntax_error()),
+        }
+    }
+}
+
+/// This function gives the value of a variable in an expression.
+/// A value that is not a number is evaluated as an expression itself.
+fn variable(name: &str, depth: usize) -> Result<i64,String> {
+    let value = shell::expand_var(name).unwrap_or_default();
+    let value = value.trim();
+    if value.is_empty() {
+        return Ok(0);
+    }
+    match value.parse::<i64>() {
+        Ok(number) => Ok(number),
+        Err(_) => evaluate_depth(value, depth + 1),
+    }
+}
+
+fn assign(name: &str, value: i64) -> Result<i64,String> {
+    if !name.split("::").all(crate::lexer::is_name) {
+        return Err(format!("{}: attempted assignment to non-variable", name));
+    }
+    shell::add_var_context(&format!("{}={}", name, value));
+    Ok(value)
+}
+
+fn eval(expr: &Expr, depth: usize) -> Result<i64,String> {
+    match expr {
+        Expr::Number(number) => Ok(*number),
+        Expr::Variable(name) => variable(name, depth),
+        Expr::Unary(operator, operand) => {
+            let value = eval(operand, depth)?;
+            Ok(match *operator {
+                "-" => value.wrapping_neg(),
+                "!" => (value == 0) as i64,
+                "~" => !value,
+                _ => value,
+            })
+        },
+        // the right side of && and || and the unused branch of ?: are not evaluated,
+        // so assignments in them do not happen
+        Expr::Binary("&&", left, right) => Ok((eval(left, depth)? != 0 && eval(right, depth)? != 0) as i64),
+        Expr::Binary("||", left, right) => Ok((eval(left, depth)? != 0 || eval(right, depth)? != 0) as i64),
+        Expr::Binary(operator, left, right) => {
+            let left = eval(left, depth)?;
+            let right = eval(right, depth)?;
+            apply(operator, left, right)
+        },
+        Expr::Conditional(condition, then, otherwise) => {
+            if eval(condition, depth)? != 0 {
+                eval(then, depth)
+            }
+            else {
+                eval(otherwise, depth)
+            }
+        },
+        Expr::Assign(name, combine, value) => {
+            let value = eval(value, depth)?;
+            let value = match combine {
+                Some(operator) => apply(operator, variable(name, depth)?, value)?,
+                None => value,
+            };
+            assign(name, value)
+        },
+        Expr::Increment(name, delta, prefix) => {
+            let old = variable(name, depth)?;
+            let new = assign(name, old.wrapping_add(*delta))?;
+            Ok(if *prefix { new } else { old })
+        },
+    }
+}
+
+fn apply(operator: &str, left: i64, right: i64) -> Result<i64,String> {
+    Ok(match operator {
+        "," => right,
+        "|" => left | right,
+        "^" => left ^ right,
+        "&" => left & right,
+        "==" => (left == right) as i64,
+        "!=" => (left != right) as i64,
+        "<" => (left < right) as i64,
+        ">" => (left > right) as i64,
+        "<=" => (left <= right) as i64,
+        ">=" => (left >= right) as i64,
+        "<<" => left.wrapping_shl(right as u32),
+        ">>" => left.wrapping_shr(right as u32),
+        "+" => left.wrapping_add(right),
+        "-" => left.wrapping_sub(right),
+        "*" => left.wrapping_mul(right),
+        "/" | "%" if right == 0 => return Err("division by 0".to_string()),
+        "/" => left.wrapping_div(right),
+        "%" => left.wrapping_rem(right),
+        "**" => {
+            if right < 0 {
+                return Err("exponent less than 0".to_string());
+            }
+            left.wrapping_pow(right.min(u32::MAX as i64) as u32)
+        },
+        _ => unreachable!("unknown arithmetic operator {}", operator),
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_evaluate() {
+        assert_eq!(evaluate("1 + 2 * 3").unwrap(), 7);
+        assert_eq!(evaluate("(1 + 2) * 3").unwrap(), 9);
+        assert_eq!(evaluate("2 ** 3 ** 2").unwrap(), 512);
+        assert_eq!(evaluate("-2 ** 2").unwrap(), 4);
+        assert_eq!(evaluate("7 / 2 + 7 % 2").unwrap(), 4);
+        assert_eq!(evaluate("1 < 2 && 2 <= 2 || 0").unwrap(), 1);
+        assert_eq!(evaluate("!5 + ~0").unwrap(), -1);
+        assert_eq!(evaluate("0 ? 1 : 2 ? 3 : 4").unwrap(), 3);
+        assert_eq!(evaluate("1 << 4 | 1").unwrap(), 17);
+        assert_eq!(evaluate("0x1f + 010 + 2#101").unwrap(), 44);
+        assert_eq!(evaluate("1, 2").unwrap(), 2);
+        assert_eq!(evaluate("").unwrap(), 0);
+    }
+
+    #[test]
+    fn test_errors() {
+        assert!(evaluate("1 / 0").is_err());
+        assert!(evaluate("1 +").is_err());
+        assert!(evaluate("(1").is_err());
+        assert!(evaluate("1 2").is_err());
+        assert!(evaluate("09").is_err());
+        assert!(evaluate("2 ** -1").is_err());
+        // the division is never evaluated
+        assert_eq!(evaluate("0 && 1 / 0").unwrap(), 0);
+    }
+}
@@ -1,11 +1,11 @@
 use std::os::unix::io::RawFd;
-use nix::sys::wait::waitpid;
-use std::fs::File;
-use std::io::{Read, Write};
-use std::os::unix::io::FromRawFd;
-use crate::lexer::Lexer;
+use crate::lexer;
 use crate::shell;
-use crate::log;
+use crate::glob;
+use crate::expand;
+use crate::arith;
+use crate::condition;
+use regex::Regex;
 use lalrpop_util::lalrpop_mod;
 use std::ffi::CString;
 use core::str::Split;
@@ -44,6 +44,25 @@ pub struct AndOr {
     pub and_or: Option<Box<AndOr>>,
     pub conditional_exec: Option<ConditionalExec>,
     pub pipeline: Pipeline,
+    pub span: Span,
+}
+
+impl AndOr {
+    /// This function applies the separator that follows the AndOr in a list.
+    /// A `&` runs its last pipeline in the background once the ones before it are done.
+    pub fn separated_by(&mut self, separator: &str) {
+        if separator == "&" {
+            self.pipeline.background = true;
+        }
+    }
+}
+
+/// Where a node starts and ends in the input that it was parsed from, which the formatter uses to put
+/// comments and blank lines back.
+#[derive(Debug,Clone,Copy,Default,PartialEq)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
 }
 
 #[derive(Debug,Clone,PartialEq)]
@@ -74,8 +93,8 @@ impl PipeSequence {
 }
 
 #[derive(Debug,Clone,PartialEq)]
-pub enum Command {
-    SimpleCommand(SimpleCommand),
+pub enum Command<W = Word> {
+    SimpleCommand(SimpleCommand<W>),
     CompoundCommand(CompoundCommand, Option<RedirectList>),
     FunctionDefinition(FunctionDefinition),
 }
@@ -89,6 +108,8 @@ pub enum CompoundCommand {
     IfClause(IfClause),
     WhileClause(WhileClause),
     UntilClause(UntilClause),
+    ArithmeticCommand(ArithmeticCommand),
+    ConditionalCommand(ConditionalCommand),
 }
 
 #[derive(Debug,Clone,PartialEq)]
@@ -127,11 +148,11 @@ pub struct ForClause {
 }
 
 #[derive(Debug,Clone,PartialEq)]
-pub struct WordList(pub Vec<String>);
+pub struct WordList(pub Vec<Word>);
 
 #[derive(Debug,Clone,PartialEq)]
 pub struct CaseClause {
-    pub word: String,
+    pub word: Word,
     pub case_list: Option<CaseList>,
 }
 
@@ -142,10 +163,11 @@ pub struct CaseList(pub Vec<CaseItem>);
 pub struct CaseItem {
     pub pattern: Pattern,
     pub compound_list: Option<CompoundList>,
+    pub span: Span,
 }
 
 #[derive(Debug,Clone,PartialEq)]
-pub struct Pattern(pub Vec<String>);
+pub struct Pattern(pub Vec<Word>);
 
 #[derive(Debug,Clone,PartialEq)]
 pub struct IfClause {
@@ -190,593 +212,372 @@ pub struct BraceGroup(pub CompoundList);
 #[derive(Debug,Clone,PartialEq)]
 pub struct DoGroup(pub CompoundList);
 
+/// The expression of a `(( ))` command.
 #[derive(Debug,Clone,PartialEq)]
-pub struct SimpleCommand {
-    pub prefix: Option<Prefix>,
-    pub name: String,
-    pub suffix: Option<Suffix>,
-}
-
-#[derive(Debug,Clone,PartialEq)]
-enum QuoteType {
-    None,
-    Single,
-    Double,
-}
-
-pub trait SplitWhitespaceIgnoringQuotes {
-    fn split_whitespace_ig_qts(&self) -> Vec<&str>;
-}
-
-impl SplitWhitespaceIgnoringQuotes for str {
-    fn split_whitespace_ig_qts<'input>(&'input self) -> Vec<&'input str> {
-        //eprintln!("split_whitespace_ig_qts: {}", self);
-        let mut quote_type = QuoteType::None;
-        let mut start = 0;
-        let mut end = 0;
-        let mut splits: Vec<&'input str> = Vec::new();
-        for (i, c) in self.char_indices() {
-            match quote_type {
-                QuoteType::None => {
-                    if c == '\'' {
-                        quote_type = QuoteType::Single;
-                    } else if c == '"' {
-                        quote_type = QuoteType::Double;
-                    } else if c.is_whitespace() {
-                        end = i;
-                        splits.push(&self[start..end]);
-                        start = i + 1;
-                    }
-                }
-                QuoteType::Single => {
-                    if c == '\'' {
-                        quote_type = QuoteType::None;
-                    }
-                }
-                QuoteType::Double => {
-                    if c == '"' {
-                        quote_type = QuoteType::None;
-                    }
-                }
-            }
-        }
-        if start < self.len() {
-            splits.push(&self[start..]);
-        }
-        splits
+pub struct ArithmeticCommand(pub Word);
 
-    }
-}
+/// The expression of a `[[ ]]` command.
+#[derive(Debug,Clone,PartialEq)]
+pub struct ConditionalCommand(pub ConditionalExpression);
 
-lalrpop_mod!(pub grammar);
-impl SimpleCommand {
-    pub fn alias_lookup(&mut self) {
-        match shell::lookup_alias(&self.name) {
-            Some(alias) => {
-                let name = alias.0;
-                let args_opt = alias.1;
-                let mut args: Vec<String>;
-                if args_opt.is_some() {
-                    args = args_opt.unwrap();
-                } else {
-                    args = Vec::new();
-                }
-                self.name = name;
-                match &mut self.suffix {
-                    Some(suffix) => {
-                        suffix.word.append(&mut args);
-                    }
-                    None => {
-                        self.suffix = Some(Suffix {
-                            io_redirect: Vec::new(),
-                            word: args,
-                        });
-                    }
-                }
-            }
-            None => {}
-        }
-    }
+/// An expression of a `[[ ]]` command. Its words are expanded without being split into fields
+/// or replaced by the paths that they match. Parentheses only group and leave no trace of their own.
+#[derive(Debug,Clone,PartialEq)]
+pub enum ConditionalExpression {
+    And(Box<ConditionalExpression>, Box<ConditionalExpression>),
+    Or(Box<ConditionalExpression>, Box<ConditionalExpression>),
+    Not(Box<ConditionalExpression>),
+    /// A test of a file or a string like `-f path` or `-z string`.
+    Unary(String, Word),
+    /// A comparison like `a == pattern`, `a =~ regex` or `n -lt m`.
+    Binary(Word, String, Word),
+    /// A word on its own, which is true if it is not empty.
+    Word(Word),
+}
 
-    pub fn expand_vars(&mut self) {
-
-        if self.prefix.is_some() {
-            for word in self.prefix.as_mut().unwrap().assignment.iter_mut() {
-                if word.contains("$") {
-                    let mut split = word.split("=");
-                    let var = split.next().unwrap();
-                    let val = split.next().unwrap();
-                    let val = match shell::expand_var(val) {
-                        Some(expanded) => {
-                            expanded
-                        }
-                        None => {
-                            val.to_string()
-                        }
-                    };
-                    *word = format!("{}={}", var, val);
-                }
-            }
-        }
+/// A word of a command made of the segments that are quoted and expanded differently,
+/// so `a"$b"'c'` is a literal, a double-quoted parameter and a single-quoted string.
+#[derive(Debug,Clone,PartialEq,Default)]
+pub struct Word(pub Vec<WordSegment>);
 
-        if self.name.starts_with("$") {
-            let mut chars = self.name.chars();
-            chars.next();
-            match shell::expand_var(&chars.collect::<String>()) {
-                Some(name) => {
-                    self.name = name;
-                }
-                None => {}
-            }
-        }
-        if self.suffix.is_none() {
-            return;
-        }
-        for word in self.suffix.as_mut().unwrap().word.iter_mut() {
-            if word.starts_with("$") {
-                let mut chars = word.chars();
-                chars.next();
-                match shell::expand_var(&chars.collect::<String>()) {
-                    Some(expanded) => {
-                        *word = expanded;
-                    }
-                    None => {}
-                }
-            }
-        }
-        /*self.suffix.as_mut().unwrap().word.iter_mut().for_each(|word| {
-            println!("word: {}", word);
-            if word.starts_with("$") {
-                match shell::expand_var(&word[1..]) {
-                    Some(expanded) => {
-                        *word = expanded;
-                    }
-                    None => {}
-                }
-            }
-        });*/
-    }
+#[derive(Debug,Clone,PartialEq)]
+pub enum WordSegment {
+    /// Unquoted text, which can have tildes and pattern characters in it.
+    Literal(String),
+    /// A character that a backslash quotes outside of double quotes.
+    Escaped(char),
+    SingleQuoted(String),
+    /// The inside of double quotes, which holds literals and expansions but no quotes.
+    DoubleQuoted(Vec<WordSegment>),
+    Parameter(Parameter),
+    /// The command of a `$(...)` or backquoted command substitution.
+    CommandSubstitution(String),
+    /// The command of a process substitution. The flag is set for `<(...)`, whose output is read,
+    /// and unset for `>(...)`, which is written to.
+    ProcessSubstitution(bool, String),
+    /// The expression of a `$(( ))` expansion.
+    Arithmetic(Word),
+    /// A `${...}` expansion that cannot be parsed, which is an error once it is expanded.
+    BadSubstitution(String),
+}
 
+/// A `$name` or `${...}` expansion.
+#[derive(Debug,Clone,PartialEq)]
+pub struct Parameter {
+    pub name: String,
+    /// The subscript of an array expansion like `${name[1]}` or `${name[@]}`.
+    pub subscript: Option<Subscript>,
+    pub operation: Option<ParameterOperation>,
+}
 
-    fn create_subshell(chr: char, chars: &mut std::str::Chars) -> String {
-        let mut subshell = String::new();
+/// The subscript of an array in a `${name[...]}` expansion.
+#[derive(Debug,Clone,PartialEq)]
+pub enum Subscript {
+    /// `[@]`, which expands to every element, each as a field of its own even inside double quotes.
+    All,
+    /// `[*]`, which expands to every element joined by the first character of IFS.
+    Joined,
+    /// The index of a single element, which is an arithmetic expression, or its key in an associative array.
+    Index(Word),
+}
 
-        if chr == '$' {
-            let Some(c) = chars.next() else { panic!("Unexpected EOF") };
-            subshell.push(chr);
-            if c == '(' {
-                subshell.push(c);
-                while let Some(c) = chars.next() {
-                    if c == ')' {
-                        subshell.push(c);
-                        break;
-                    }
-                    subshell.push(c);
-                }
-                
+/// The operations of `${...}` expansions. The flag of the first four is set for the forms with a colon,
+/// which treat a variable with an empty value the same as an unset one.
+/// The flag of the last two is set for `##` and `%%`, which remove the longest match instead of the shortest.
+#[derive(Debug,Clone,PartialEq)]
+pub enum ParameterOperation {
+    /// `${#name}`, or `${#name[@]}` for the number of elements of an array.
+    Length,
+    /// `${!name[@]}`, the indices or keys of the elements of an array.
+    Keys,
+    /// `${name:-word}`
+    Default(bool, Word),
+    /// `${name:=word}`
+    Assign(bool, Word),
+    /// `${name:?word}`
+    Error(bool, Word),
+    /// `${name:+word}`
+    Alternative(bool, Word),
+    /// `${name#pattern}`
+    RemovePrefix(bool, Word),
+    /// `${name%pattern}`
+    RemoveSuffix(bool, Word),
+}
 
-            }
+impl Word {
+    /// This function gives the text of a word that is a single unquoted literal without any expansions.
+    pub fn as_literal(&self) -> Option<&str> {
+        match self.0.as_slice() {
+            [WordSegment::Literal(text)] => Some(text),
+            _ => None,
         }
-        else {
-            subshell.push(chr);
-            while let Some(c) = chars.next() {
-                if c == '`' {
-                    subshell.push(c);
-                    break;
-                }
-                subshell.push(c);
-            }
-        }
-
-        subshell
     }
+}
 
-    fn eval_subshell(subshell: &str) -> String {
-
-        let mut chars = subshell.chars();
-
-        if subshell.starts_with("$(") {
-            chars.next();
-            chars.next();
-            chars.next_back();
-        }
-        else if subshell.starts_with("`"){
-            chars.next();
-            chars.next_back();
-        }
-        else {
-            return subshell.to_string();
-        }
-
-        let subshell = &chars.collect::<String>();
-
-        let lexer = Lexer::new(&subshell);
-        let mut ast = grammar::CompleteCommandParser::new()
-            .parse(&subshell,lexer)
-            .unwrap();
-
-        let pip: (RawFd,RawFd) = nix::unistd::pipe().unwrap();
-
-        match unsafe {nix::unistd::fork().expect("failed to fork")} {
-            nix::unistd::ForkResult::Parent { child } => {
-                nix::unistd::close(pip.1).unwrap();
-                let mut buf = String::new();
-                let mut file = unsafe { File::from_raw_fd(pip.0) };
-                waitpid(child, None).unwrap();
-                file.read_to_string(&mut buf).unwrap();
-                nix::unistd::close(pip.0).unwrap();
-                buf.trim().to_string()
-            },
-            nix::unistd::ForkResult::Child => {
-                nix::unistd::dup2(pip.1, 1).unwrap();
-                nix::unistd::dup2(pip.1, 2).unwrap();
-                nix::unistd::close(pip.1).unwrap();
-                nix::unistd::close(pip.0).unwrap();
-                let _ = crate::eval::eval(&mut ast);
-                std::process::exit(0);
-            }
-        }
-    }
+/// A simple command as it is parsed, with words made of segments.
+/// Expanding it gives a `SimpleCommand<String>` that has the fields it expanded to, which is what gets run.
+#[derive(Debug,Clone,PartialEq)]
+pub struct SimpleCommand<W = Word> {
+    pub prefix: Option<Prefix<W>>,
+    pub name: W,
+    pub suffix: Option<Suffix<W>>,
+}
 
-    fn recombine_double_quotes(words: &mut Vec<&str>) -> Vec<String>{
-        let mut new_words = Vec::new();
-        let mut in_quotes = false;
-        let mut index = 0;
-        for word in words.iter() {
-            if word.starts_with("\"") {
-                in_quotes = true;
-                new_words.push(word.to_string());
-            }
-            else if in_quotes {
-                new_words[index].push_str(" ");
-                new_words[index].push_str(word);
-                if word.ends_with("\"") {
-                    in_quotes = false;
-                }
-            }
-            else {
-                index += 1;
-                new_words.push(word.to_string());
+lalrpop_mod!(pub grammar);
+impl SimpleCommand {
+    /// This function replaces the name of the command with the alias it is, if any.
+    /// The words of the alias come before the arguments of the command.
+    pub fn alias_lookup(&mut self) {
+        let alias = match self.name.as_literal().and_then(shell::lookup_alias) {
+            Some(alias) => alias,
+            None => return,
+        };
+        self.name = lexer::parse_word(&alias.0);
+        let mut args: Vec<Word> = alias.1.unwrap_or_default().iter().map(|arg| lexer::parse_word(arg)).collect();
+        match &mut self.suffix {
+            Some(suffix) => {
+                args.append(&mut suffix.word);
+                suffix.word = args;
             }
-        }
-        new_words
-    }
-
-
-    pub fn remove_whitespace(&mut self) {
-        //eprintln!("suffix: {:?}", self.suffix);
-        let temp = self.name.clone();
-        let mut words;
-        if !temp.contains('\''){
-            words = temp.split_whitespace().collect::<Vec<&str>>();
-            let mut new_words = if temp.contains("\"") {
-		log!("recombine_double_quotes");
-                Self::recombine_double_quotes(&mut words)
-            } 
-            else {
-                words.iter().map(|word| word.to_string()).collect::<Vec<String>>()
-            };
-
-            self.name = new_words.remove(0);
-
-            if self.suffix.is_none() {
+            None => {
                 self.suffix = Some(Suffix {
                     io_redirect: Vec::new(),
-                    word: new_words,
+                    word: args,
                 });
-                return;
-            }
-            else {
-                let mut words = new_words;
-                for word in self.suffix.as_ref().unwrap().word.iter() {
-                    if word.contains('\'') {
-                        words.push(word.to_string());
-                    }
-                    else {
-                        let mut temp_words = word.split_whitespace().collect::<Vec<&str>>();
-                        let mut new_words = if word.contains("\"") {
-			    log!("recombine_double_quotes");
-                            Self::recombine_double_quotes(&mut temp_words)
-                        } 
-                        else {
-                            temp_words.iter().map(|word| word.to_string()).collect::<Vec<String>>()
-                        };
-                        words.append(&mut new_words);
-                    }
-                }
-
-                self.suffix.as_mut().unwrap().word = words;
             }
         }
     }
 
-
-    /*
-     * The logic of this is disgusting since other shells will split at whitespace and we do it
-     * here. We basically rip apart every string in both the name and suffix and then recombine
-     * them.
-     * 
-     */
-    pub fn expand_subshells(&mut self) {
-        //eprintln!("expand_subshells");
-        if self.prefix.is_some() {
-            for word in self.prefix.as_mut().unwrap().assignment.iter_mut() {
-                if (word.contains("$(") && word.ends_with(")")) || (word.contains("`") && word.ends_with("`")) {
-                    let mut split = word.split("=");
-                    let var = split.next().unwrap();
-                    let val = split.next().unwrap();
-                    let val = Self::eval_subshell(val);
-                    *word = format!("{}={}", var, val);
-                }
+    /// This function expands the words of the command in the order POSIX gives:
+    /// the name and the arguments first, then the filenames of the redirections and then the values of the assignments.
+    /// The name and the arguments can expand to any number of fields and the first field becomes the name,
+    /// while every other word is expanded to a single word.
+    pub fn expand(&self) -> Result<SimpleCommand<String>,String> {
+        let mut words = expand::expand_word(&self.name)?;
+        if let Some(suffix) = &self.suffix {
+            for word in suffix.word.iter() {
+                words.append(&mut expand::expand_word(word)?);
             }
         }
 
-        if (self.name.starts_with("$(") && self.name.ends_with(")")) || (self.name.starts_with("`") && self.name.ends_with("`")) {
-            self.name = Self::eval_subshell(&self.name);
-        }
-
-        //let temp = self.name.clone();
-        //let mut words = temp.split_whitespace().collect::<Vec<&str>>();
-        //self.name = words.remove(0).to_string();
-
-        /*if self.suffix.is_none() {
-            self.suffix = Some(Suffix {
-                io_redirect: Vec::new(),
-                word: words.iter().map(|word| word.to_string()).collect(),
-            });
-            return;
-        }*/
-
-        //let mut words = words.iter().map(|word| word.to_string()).collect::<Vec<String>>();
+        let prefix_redirects = match &self.prefix {
+            Some(prefix) => expand_redirects(&prefix.io_redirect)?,
+            None => Vec::new(),
+        };
+        let suffix_redirects = match &self.suffix {
+            Some(suffix) => expand_redirects(&suffix.io_redirect)?,
+            None => Vec::new(),
+        };
+
+        let prefix = match &self.prefix {
+            Some(prefix) => {
+                let mut assignment = Vec::new();
+                for assign in prefix.assignment.iter() {
+                    assignment.push(expand::expand_assignment(assign)?);
+                }
+                Some(Prefix { io_redirect: prefix_redirects, assignment })
+            },
+            None => None,
+        };
+
+        let name = if words.is_empty() { String::new() } else { words.remove(0) };
+        Ok(SimpleCommand {
+            prefix,
+            name,
+            suffix: Some(Suffix {
+                io_redirect: suffix_redirects,
+                word: words,
+            }),
+        })
+    }
+}
 
-        if self.suffix.is_none() {
-            return;
-        }
-        for word in self.suffix.as_mut().unwrap().word.iter_mut() {
-            if (word.starts_with("$(") && word.ends_with(")")) || (word.starts_with("`") && word.ends_with("`")) {
-                *word = Self::eval_subshell(word);
+impl SimpleCommand<String> {
+    pub fn argv(&self) -> Vec<CString> {
+        let mut argv = Vec::new();
+        argv.push(CString::new(self.name.clone()).unwrap());
+        
+        if self.suffix.is_some() {
+            for word in self.suffix.as_ref().unwrap().word.iter() {
+                argv.push(CString::new(word.clone()).unwrap());
             }
         }
 
-        /*for word in self.suffix.as_ref().unwrap().word.iter() {
-            let temp_words = word.split_whitespace().collect::<Vec<&str>>();
-            
-            words.append(&mut temp_words.iter().map(|word| word.to_string()).collect::<Vec<String>>());
-        }*/
-
-        //self.suffix.as_mut().unwrap().word = words;
-
-
+        argv
     }
 
-    fn cut_quotes(word: &mut String) {
-        let mut chars = word.chars();
-        let mut try_expand_subshell = false;
-        if chars.next() == Some('"') {
-            try_expand_subshell = true;
-        }
-        chars.next_back();
-
+    pub fn cmd(&self) -> String {
+        let mut cmd = String::new();
+        cmd.push_str(&self.name);
 
-        if try_expand_subshell {
-            let mut new_word = String::new();
-            while let Some(chr) = chars.next()  {
-                if chr == '$' || chr == '`' {
-                    let subshell = Self::create_subshell(chr,&mut chars);
-                    
-                    new_word = new_word + &Self::eval_subshell(&subshell);
-                }
-                else {
-                    new_word.push(chr);
-                }
-                
+        /*if self.suffix.is_some() {
+            for word in self.suffix.as_ref().unwrap().word.iter() {
+                cmd.push_str(" ");
+                cmd.push_str(&word);
             }
+        }*/
 
-            *word = new_word;
-            return;
-        }
+        cmd
+    }
 
-        *word = chars.collect::<String>();
+    pub fn prefix_suffix(&self) -> (Option<&Prefix<String>>, Option<&Suffix<String>>) {
+        let prefix = self.prefix.as_ref();
+        let suffix = self.suffix.as_ref();
+        (prefix, suffix)
     }
+}
 
-    pub fn eval(&mut self) {
-        if self.name.as_str() == "" {
-            return;
-        }
-        if self.name.starts_with('\'') {
-            Self::cut_quotes(&mut self.name);
-        }
+impl WordList {
+    /// This function expands the words of the list the same way that the arguments of a SimpleCommand are expanded.
+    pub fn expand(&self) -> Result<Vec<String>,String> {
         let mut words = Vec::new();
-        for word in self.name.split_whitespace_ig_qts().iter() {
-            if word.starts_with('"') {
-                words.append(&mut Self::eval_double_quotes(word));
-            }
-            else {
-                words.push(word.to_string());
-            }
+        for word in self.0.iter() {
+            words.append(&mut expand::expand_word(word)?);
         }
-        self.name = words.remove(0);
-        
-        if self.suffix.is_none() || self.suffix.as_ref().unwrap().word.is_empty() {
-            self.suffix = Some(Suffix {
-                io_redirect: Vec::new(),
-                word: words,
-            });
-            return;
-        }
-        else {
-            let mut new_words = words;
-            for word in self.suffix.as_ref().unwrap().word.iter() {
-                if word.starts_with('"') {
-                    new_words.append(&mut Self::eval_double_quotes(word));
-                }
-                else if word.starts_with('\'') {
-                    let mut new_word = word.to_string();
-                    Self::cut_quotes(&mut new_word);
-                    new_words.push(new_word);
-                }
-                else {
-                    new_words.push(word.to_string());
-                }
-            }
-            self.suffix.as_mut().unwrap().word = new_words;
-        }
-
+        Ok(words)
     }
+}
 
-    fn eval_double_quotes(word: &str) -> Vec<String> {
-        if !word.contains("$(") && !word.contains("`") {
-            let mut new_word = word.to_string();
-            Self::cut_quotes(&mut new_word);
-            return vec![new_word];
-        }
-        let mut new_word = word.to_string();
-        Self::cut_quotes(&mut new_word);
-        let mut ret = Vec::new();
-        let eval = Self::eval_subshell(&new_word);
-        if eval.contains(" ") {
-            ret.append(&mut eval.split_whitespace().map(|word| word.to_string()).collect());
-        }
-        else {
-            ret.push(eval);
-        }
-        ret
+impl RedirectList {
+    /// This function expands the filenames of the redirections of a compound command.
+    pub fn expand(&self) -> Result<Vec<IoRedirect<String>>,String> {
+        expand_redirects(&self.0)
     }
+}
 
-    /*pub fn eval_double_quotes(&mut self) {
-
-        eprintln!("\n\n{:?}", self.name.split_whitespace_ig_qts());
-
+/// This function expands the filenames of redirections.
+/// The body of a here-document is expanded separately when it is written out.
+fn expand_redirects(io_redirect: &[IoRedirect]) -> Result<Vec<IoRedirect<String>>,String> {
+    let mut expanded = Vec::new();
+    for redirect in io_redirect.iter() {
+        let io_file = match &redirect.io_file {
+            Some(io_file) => Some(IoFile {
+                redirect_type: io_file.redirect_type.clone(),
+                filename: expand::expand_word_single(&io_file.filename)?,
+            }),
+            None => None,
+        };
+        expanded.push(IoRedirect {
+            io_number: redirect.io_number,
+            io_file,
+            io_here: redirect.io_here.clone(),
+        });
+    }
+    Ok(expanded)
+}
 
-        if (self.name.starts_with("\"")) && (self.name.ends_with("\"")) {
-            Self::cut_quotes(&mut self.name);
+impl IoHere {
+    pub fn new(delimiter: &str, here: &str, strip_tabs: bool) -> Self {
+        let expand = !delimiter.contains(['\'', '"', '\\']);
+        IoHere { delimiter: delimiter.to_string(), here: here.to_string(), strip_tabs, expand }
+    }
 
-        }
-        
-        self.name = Self::eval_subshell(&self.name);
-        let mut words;
-        let name = self.name.clone();
-        if self.name.contains(" ") {
-            words = name.split_whitespace().collect::<Vec<&str>>();
-            self.name = words.remove(0).to_string();
-        }
-        else {
-            words = Vec::new();
-        }
-        if self.suffix.is_none() {
-            if words.len() > 0 {
-                self.suffix = Some(Suffix {
-                    io_redirect: Vec::new(),
-                    word: words.iter().map(|word| word.to_string()).collect(),
-                });
-            }
-            return;
-        }
-        if self.suffix.is_some() {
-            if self.suffix.as_ref().unwrap().word.len() > 0 {
-                eprintln!("{:?}\n\n", self.suffix.as_ref().unwrap().word[0].split_whitespace_ig_qts());
-            }
-            else {
-                eprintln!("\n\n");
-            }
-        }
-        else {
-            eprintln!("\n\n");
-        }
-        for word in self.suffix.as_mut().unwrap().word.iter_mut() {
-            if (word.starts_with("\"")) && (word.ends_with("\"")) {
-                Self::cut_quotes(word);
-                if word.contains("$(") || word.contains("`") {
-                    *word = Self::eval_subshell(word);
-                    if word.contains(" ") {
-                        let mut temp_words = word.split_whitespace().collect::<Vec<&str>>();
-                        words.append(&mut temp_words);
-                    }
-                }
-                else {
-                    words.push(word);
-                }
+    /// This function gives the text of a here-document the way it is fed to the command.
+    /// Variables, command substitutions and backslash escapes are expanded unless the delimiter was quoted.
+    pub fn body(&self) -> Result<String,String> {
+        let mut body = String::with_capacity(self.here.len());
+        for line in self.here.split_inclusive('\n') {
+            if self.strip_tabs {
+                body.push_str(line.trim_start_matches('\t'));
             }
             else {
-                words.push(word);
+                body.push_str(line);
             }
         }
-        self.suffix.as_mut().unwrap().word = words.iter().map(|word| word.to_string()).collect();
-    }*/
-
-    pub fn remove_double_quotes(&mut self) {
-        if (self.name.starts_with("\"")) && (self.name.ends_with("\"")) {
-            Self::cut_quotes(&mut self.name);
-        }
-        if self.suffix.is_none() {
-            return;
+        if self.expand {
+            expand::expand_here_document(&lexer::parse_here_document(&body))
         }
-        for word in self.suffix.as_mut().unwrap().word.iter_mut() {
-            if (word.starts_with("\"")) && (word.ends_with("\"")) {
-                Self::cut_quotes(word);
-            }
+        else {
+            Ok(body)
         }
     }
-    pub fn remove_single_quotes(&mut self) {
-        if (self.name.starts_with("'")) && (self.name.ends_with("'")) {
-            Self::cut_quotes(&mut self.name);
-        }
-        if self.suffix.is_none() {
-            return;
-        }
-        for word in self.suffix.as_mut().unwrap().word.iter_mut() {
-            if (word.starts_with("'")) && (word.ends_with("'")) {
-                Self::cut_quotes(word);
+}
+
+impl Pattern {
+    /// This function checks if a word matches any of the alternatives of a case pattern.
+    /// Quoted parts of a pattern match literally while expanded variables keep their special characters.
+    pub fn matches(&self, word: &str) -> Result<bool,String> {
+        for alternative in self.0.iter() {
+            if glob::pattern_match(&expand::expand_pattern(alternative)?, word) {
+                return Ok(true);
             }
         }
+        Ok(false)
     }
+}
 
-
-
-    pub fn argv(&self) -> Vec<CString> {
-        let mut argv = Vec::new();
-        argv.push(CString::new(self.name.clone()).unwrap());
-        
-        if self.suffix.is_some() {
-            for word in self.suffix.as_ref().unwrap().word.iter() {
-                argv.push(CString::new(word.clone()).unwrap());
-            }
+impl ConditionalExpression {
+    /// This function evaluates the expression of a `[[ ]]` command.
+    /// `&&` and `||` only expand the words on their right when they are needed. The right of `==` and `!=`
+    /// is a pattern and that of `=~` a regular expression, where quoted parts match literally,
+    /// and both sides of an integer comparison are arithmetic expressions.
+    pub fn test(&self) -> Result<bool,String> {
+        match self {
+            ConditionalExpression::And(lhs, rhs) => Ok(lhs.test()? && rhs.test()?),
+            ConditionalExpression::Or(lhs, rhs) => Ok(lhs.test()? || rhs.test()?),
+            ConditionalExpression::Not(expression) => Ok(!expression.test()?),
+            ConditionalExpression::Word(word) => Ok(!expand::expand_word_single(word)?.is_empty()),
+            ConditionalExpression::Unary(operator, word) => condition::unary_test(operator, &expand::expand_word_single(word)?),
+            ConditionalExpression::Binary(lhs, operator, rhs) => {
+                let lhs = expand::expand_word_single(lhs)?;
+                match operator.as_str() {
+                    "=" | "==" => Ok(glob::pattern_match(&expand::expand_pattern(rhs)?, &lhs)),
+                    "!=" => Ok(!glob::pattern_match(&expand::expand_pattern(rhs)?, &lhs)),
+                    "=~" => regex_match(&expand::expand_regex(rhs)?, &lhs),
+                    operator if condition::INTEGER_OPERATORS.contains(&operator) => {
+                        let rhs = expand::expand_word_single(rhs)?;
+                        Ok(condition::compare(arith::evaluate(&lhs)?, operator, arith::evaluate(&rhs)?))
+                    },
+                    operator => condition::binary_test(&lhs, operator, &expand::expand_word_single(rhs)?),
+                }
+            },
         }
-
-        argv
     }
+}
 
-    pub fn cmd(&self) -> String {
-        let mut cmd = String::new();
-        cmd.push_str(&self.name);
-
-        /*if self.suffix.is_some() {
-            for word in self.suffix.as_ref().unwrap().word.iter() {
-                cmd.push_str(" ");
-                cmd.push_str(&word);
-            }
-        }*/
+/// This function matches a string against the extended regular expression of `=~`.
+/// The part of the string that matched and then the parts that the groups of the expression matched
+/// are put into the BASH_REMATCH array, which is left empty if the string does not match.
+fn regex_match(pattern: &str, text: &str) -> Result<bool,String> {
+    let regex = Regex::new(pattern).map_err(|_| format!("{}: invalid regular expression", pattern))?;
+    let groups: Vec<String> = match regex.captures(text) {
+        Some(captures) => captures.iter().map(|group| group.map_or("", |group| group.as_str()).to_string()).collect(),
+        None => Vec::new(),
+    };
+    let matched = !groups.is_empty();
+    shell::set_array("BASH_REMATCH", groups, false);
+    Ok(matched)
+}
 
-        cmd
-    }
+#[derive(Debug,Clone,PartialEq)]
+pub struct Prefix<W = Word> {
+    pub io_redirect: Vec<IoRedirect<W>>,
+    pub assignment: Vec<Assignment<W>>
+}
 
-    pub fn prefix_suffix(&self) -> (Option<&Prefix>, Option<&Suffix>) {
-        let prefix = self.prefix.as_ref();
-        let suffix = self.suffix.as_ref();
-        (prefix, suffix)
-    }
+/// An assignment like `name=value`, `name[index]=value` or `name=(a b c)`.
+/// The index is left as the text of its expression until it is assigned,
+/// as only then is it known what kind of variable it indexes.
+#[derive(Debug,Clone,PartialEq)]
+pub struct Assignment<W = Word> {
+    pub name: String,
+    pub index: Option<W>,
+    /// Set for `+=`, which appends to the value instead of replacing it.
+    pub append: bool,
+    pub value: AssignmentValue<W>,
 }
 
 #[derive(Debug,Clone,PartialEq)]
-pub struct Prefix {
-    pub io_redirect: Vec<IoRedirect>,
-    pub assignment: Vec<String>
+pub enum AssignmentValue<W = Word> {
+    Scalar(W),
+    /// The words of `name=(...)`, which become the elements of an indexed array.
+    Array(Vec<W>),
 }
 
 #[derive(Debug,Clone,PartialEq)]
-pub struct Suffix {
-    pub io_redirect: Vec<IoRedirect>,
-    pub word: Vec<String>,
+pub struct Suffix<W = Word> {
+    pub io_redirect: Vec<IoRedirect<W>>,
+    pub word: Vec<W>,
 }
 
 #[derive(Debug,Clone,PartialEq)]
@@ -784,9 +585,9 @@ pub struct RedirectList(pub Vec<IoRedirect>);
 
 
 #[derive(Debug,Clone,PartialEq)]
-pub struct IoRedirect {
+pub struct IoRedirect<W = Word> {
     pub io_number: Option<RawFd>,
-    pub io_file: Option<IoFile>,
+    pub io_file: Option<IoFile<W>>,
     pub io_here: Option<IoHere>,
 }
 
@@ -796,17 +597,28 @@ pub enum RedirectType {
     Output,
     Append,
     Clobber,
+    /// `<>` opens the file for reading and writing.
+    ReadWrite,
+    /// `<&` and `>&` copy the file descriptor named by the filename or close it for `-`.
+    DuplicateInput,
+    DuplicateOutput,
 }
 
 #[derive(Debug,Clone,PartialEq)]
-pub struct IoFile {
+pub struct IoFile<W = Word> {
     pub redirect_type: RedirectType,
-    pub filename: String,
+    pub filename: W,
 }
 
 #[derive(Debug,Clone,PartialEq)]
 pub struct IoHere {
+    /// The delimiter as it was written, quotes and all.
+    pub delimiter: String,
     pub here: String,
+    /// Set for `<<-`, which strips the leading tabs from every line of the body.
+    pub strip_tabs: bool,
+    /// The body is only expanded if no part of the delimiter was quoted.
+    pub expand: bool,
 }
 
 #[derive(Debug,Clone,PartialEq)]
@@ -815,10 +627,20 @@ pub struct NewlineList {
 }
 
 
+#[cfg(test)]
 mod test {
     use super::*;
+    use crate::lexer::Lexer;
     lalrpop_mod!(pub grammar);
 
+    fn literal(text: &str) -> Word {
+        Word(vec![WordSegment::Literal(text.to_string())])
+    }
+
+    fn parameter(name: &str, operation: Option<ParameterOperation>) -> WordSegment {
+        WordSegment::Parameter(Parameter { name: name.to_string(), subscript: None, operation })
+    }
+
     #[test]
     fn test_parser() {
         let input = "echo Hello world";
@@ -968,4 +790,474 @@ mod test {
             .unwrap();
         println!("{:#?}", ast);
     }
+
+    #[test]
+    fn test_if() {
+        let input = "if true; then echo yes; fi";
+        let lexer = Lexer::new(input);
+        let ast = grammar::CompleteCommandParser::new()
+            .parse(input,lexer)
+            .unwrap();
+        println!("{:#?}", ast);
+    }
+
+    #[test]
+    fn test_if_elif_else_multiline() {
+        let input = "if false\nthen\n  echo a\nelif true; then\n  echo b;\n  echo c\nelse\n  echo d\nfi\n";
+        let lexer = Lexer::new(input);
+        let ast = grammar::CompleteCommandParser::new()
+            .parse(input,lexer)
+            .unwrap();
+        println!("{:#?}", ast);
+        let list = ast.list.unwrap();
+        match &list.0[0].pipeline.pipe_sequence.0[0] {
+            Command::CompoundCommand(CompoundCommand::IfClause(if_clause), _) => {
+                assert_eq!(if_clause.else_part.len(), 2);
+                assert!(if_clause.else_part[0].condition.is_some());
+                assert!(if_clause.else_part[1].condition.is_none());
+            },
+            _ => panic!("expected an if clause"),
+        }
+    }
+
+    #[test]
+    fn test_nested_if() {
+        let input = "if true; then if false; then echo a; fi; fi";
+        let lexer = Lexer::new(input);
+        let ast = grammar::CompleteCommandParser::new()
+            .parse(input,lexer)
+            .unwrap();
+        println!("{:#?}", ast);
+    }
+
+    #[test]
+    fn test_reserved_word_as_argument() {
+        let input = "echo if then fi";
+        let lexer = Lexer::new(input);
+        let ast = grammar::CompleteCommandParser::new()
+            .parse(input,lexer)
+            .unwrap();
+        println!("{:#?}", ast);
+    }
+
+    #[test]
+    fn test_while() {
+        let input = "while true; do echo loop; break; done";
+        let lexer = Lexer::new(input);
+        let ast = grammar::CompleteCommandParser::new()
+            .parse(input,lexer)
+            .unwrap();
+        println!("{:#?}", ast);
+    }
+
+    #[test]
+    fn test_until_multiline() {
+        let input = "until test -f file\ndo\n  while true; do\n    continue 2\n  done\ndone\n";
+        let lexer = Lexer::new(input);
+        let ast = grammar::CompleteCommandParser::new()
+            .parse(input,lexer)
+            .unwrap();
+        println!("{:#?}", ast);
+        let list = ast.list.unwrap();
+        assert!(matches!(list.0[0].pipeline.pipe_sequence.0[0], Command::CompoundCommand(CompoundCommand::UntilClause(_), _)));
+    }
+
+    #[test]
+    fn test_for() {
+        let input = "for x in a b c; do echo $x; done";
+        let lexer = Lexer::new(input);
+        let ast = grammar::CompleteCommandParser::new()
+            .parse(input,lexer)
+            .unwrap();
+        println!("{:#?}", ast);
+        let list = ast.list.unwrap();
+        match &list.0[0].pipeline.pipe_sequence.0[0] {
+            Command::CompoundCommand(CompoundCommand::ForClause(for_clause), _) => {
+                match &for_clause.for_type {
+                    ForType::ForClauseList(list) => {
+                        assert_eq!(list.name, "x");
+                        assert_eq!(list.word_list.0, vec![literal("a"), literal("b"), literal("c")]);
+                    },
+                    _ => panic!("expected a word list"),
+                }
+            },
+            _ => panic!("expected a for clause"),
+        }
+    }
+
+    #[test]
+    fn test_for_positional() {
+        let input = "for arg\ndo\n  echo $arg\ndone\nfor arg; do echo $arg; done";
+        let lexer = Lexer::new(input);
+        let ast = grammar::CompleteCommandParser::new()
+            .parse(input,lexer)
+            .unwrap();
+        println!("{:#?}", ast);
+    }
+
+    #[test]
+    fn test_for_reserved_words_in_list() {
+        let input = "for in in do done in; do echo $in; done";
+        let lexer = Lexer::new(input);
+        let ast = grammar::CompleteCommandParser::new()
+            .parse(input,lexer)
+            .unwrap();
+        println!("{:#?}", ast);
+    }
+
+    #[test]
+    fn test_case() {
+        let input = "case $x in\n  a|b)\n    echo ab\n    ;;\n  (c*) echo c;;\n  *) echo default\nesac";
+        let lexer = Lexer::new(input);
+        let ast = grammar::CompleteCommandParser::new()
+            .parse(input,lexer)
+            .unwrap();
+        println!("{:#?}", ast);
+        let list = ast.list.unwrap();
+        match &list.0[0].pipeline.pipe_sequence.0[0] {
+            Command::CompoundCommand(CompoundCommand::CaseClause(case_clause), _) => {
+                assert_eq!(case_clause.word, Word(vec![parameter("x", None)]));
+                let items = &case_clause.case_list.as_ref().unwrap().0;
+                assert_eq!(items.len(), 3);
+                assert_eq!(items[0].pattern.0, vec![literal("a"), literal("b")]);
+                assert_eq!(items[1].pattern.0, vec![literal("c*")]);
+                assert_eq!(items[2].pattern.0, vec![literal("*")]);
+            },
+            _ => panic!("expected a case clause"),
+        }
+    }
+
+    #[test]
+    fn test_case_empty() {
+        let input = "case word in esac; case word in\nin) echo in;; esac";
+        let lexer = Lexer::new(input);
+        let ast = grammar::CompleteCommandParser::new()
+            .parse(input,lexer)
+            .unwrap();
+        println!("{:#?}", ast);
+    }
+
+    #[test]
+    fn test_subshell() {
+        let input = "(cd /tmp; ls) > out | cat &";
+        let lexer = Lexer::new(input);
+        let ast = grammar::CompleteCommandParser::new()
+            .parse(input,lexer)
+            .unwrap();
+        println!("{:#?}", ast);
+        let list = ast.list.unwrap();
+        assert!(list.0[0].pipeline.background);
+        match &list.0[0].pipeline.pipe_sequence.0[0] {
+            Command::CompoundCommand(CompoundCommand::SubShell(subshell), Some(redirect_list)) => {
+                assert_eq!(subshell.compound_list.0.0.len(), 2);
+                assert_eq!(redirect_list.0.len(), 1);
+            },
+            _ => panic!("expected a subshell with a redirect"),
+        }
+    }
+
+    #[test]
+    fn test_nested_subshell() {
+        let input = "( (echo a)\n  echo b\n)";
+        let lexer = Lexer::new(input);
+        let ast = grammar::CompleteCommandParser::new()
+            .parse(input,lexer)
+            .unwrap();
+        println!("{:#?}", ast);
+    }
+
+    #[test]
+    fn test_here_document() {
+        let input = "cat <<EOF; cat <<-'END'\nhello $name\nEOF\n\tbye\n\tEND\necho done";
+        let lexer = Lexer::new(input);
+        let ast = grammar::CompleteCommandParser::new()
+            .parse(input,lexer)
+            .unwrap();
+        println!("{:#?}", ast);
+        let list = ast.list.unwrap();
+        assert_eq!(list.0.len(), 3);
+        let here_document = |index: usize| {
+            match &list.0[index].pipeline.pipe_sequence.0[0] {
+                Command::SimpleCommand(command) => command.suffix.as_ref().unwrap().io_redirect[0].io_here.clone().unwrap(),
+                _ => panic!("expected a simple command"),
+            }
+        };
+        let first = here_document(0);
+        assert_eq!(first.here, "hello $name\n");
+        assert!(first.expand);
+        let second = here_document(1);
+        assert_eq!(second.body().unwrap(), "bye\n");
+        assert!(!second.expand);
+    }
+
+    #[test]
+    fn test_fd_redirections() {
+        let input = "cmd 2>&1 >out 3<>file 4<&0 >&-";
+        let lexer = Lexer::new(input);
+        let ast = grammar::CompleteCommandParser::new()
+            .parse(input,lexer)
+            .unwrap();
+        println!("{:#?}", ast);
+        let list = ast.list.unwrap();
+        match &list.0[0].pipeline.pipe_sequence.0[0] {
+            Command::SimpleCommand(command) => {
+                let redirects: Vec<(Option<RawFd>, RedirectType, Word)> = command.suffix.as_ref().unwrap().io_redirect.iter()
+                    .map(|redirect| {
+                        let io_file = redirect.io_file.clone().unwrap();
+                        (redirect.io_number, io_file.redirect_type, io_file.filename)
+                    })
+                    .collect();
+                assert_eq!(redirects, vec![
+                    (Some(2), RedirectType::DuplicateOutput, literal("1")),
+                    (None, RedirectType::Output, literal("out")),
+                    (Some(3), RedirectType::ReadWrite, literal("file")),
+                    (Some(4), RedirectType::DuplicateInput, literal("0")),
+                    (None, RedirectType::DuplicateOutput, literal("-")),
+                ]);
+            },
+            _ => panic!("expected a simple command"),
+        }
+    }
+
+    #[test]
+    fn test_parameter_expansion_words() {
+        let input = "x=1 y=\"a b\" echo ${x:-a b}post \"q ${y#*}\" a=b $(echo ')')";
+        let lexer = Lexer::new(input);
+        let ast = grammar::CompleteCommandParser::new()
+            .parse(input,lexer)
+            .unwrap();
+        println!("{:#?}", ast);
+        let list = ast.list.unwrap();
+        match &list.0[0].pipeline.pipe_sequence.0[0] {
+            Command::SimpleCommand(command) => {
+                assert_eq!(command.prefix.as_ref().unwrap().assignment, vec![
+                    Assignment { name: "x".to_string(), index: None, append: false, value: AssignmentValue::Scalar(literal("1")) },
+                    Assignment {
+                        name: "y".to_string(),
+                        index: None,
+                        append: false,
+                        value: AssignmentValue::Scalar(Word(vec![WordSegment::DoubleQuoted(vec![WordSegment::Literal("a b".to_string())])])),
+                    },
+                ]);
+                assert_eq!(command.name, literal("echo"));
+                assert_eq!(command.suffix.as_ref().unwrap().word, vec![
+                    Word(vec![
+                        parameter("x", Some(ParameterOperation::Default(true, literal("a b")))),
+                        WordSegment::Literal("post".to_string()),
+                    ]),
+                    Word(vec![WordSegment::DoubleQuoted(vec![
+                        WordSegment::Literal("q ".to_string()),
+                        parameter("y", Some(ParameterOperation::RemovePrefix(false, literal("*")))),
+                    ])]),
+                    literal("a=b"),
+                    Word(vec![WordSegment::CommandSubstitution("echo ')'".to_string())]),
+                ]);
+            },
+            _ => panic!("expected a simple command"),
+        }
+    }
+
+    #[test]
+    fn test_arithmetic_command() {
+        let input = "((x = (1 + 2) * 3)) && echo $((x > 8))\n((echo a) | cat)";
+        let lexer = Lexer::new(input);
+        let ast = grammar::CompleteCommandParser::new()
+            .parse(input,lexer)
+            .unwrap();
+        println!("{:#?}", ast);
+        let list = ast.list.unwrap();
+        let and_or = list.0[0].and_or.as_ref().unwrap();
+        assert_eq!(and_or.pipeline.pipe_sequence.0[0], Command::CompoundCommand(CompoundCommand::ArithmeticCommand(ArithmeticCommand(literal("x = (1 + 2) * 3"))), None));
+        match &list.0[0].pipeline.pipe_sequence.0[0] {
+            Command::SimpleCommand(command) => assert_eq!(command.suffix.as_ref().unwrap().word, vec![Word(vec![WordSegment::Arithmetic(literal("x > 8"))])]),
+            _ => panic!("expected a simple command"),
+        }
+        assert!(matches!(list.0[1].pipeline.pipe_sequence.0[0], Command::CompoundCommand(CompoundCommand::SubShell(_), _)));
+    }
+
+    #[test]
+    fn test_word_segments() {
+        let input = "echo a\\\\\"$b\"'c' \"${#x}${y:=\"z\"}\"d$1e${ns::v%%.*}$(( $n + 1 ))`echo \\`hi\\``";
+        let lexer = Lexer::new(input);
+        let ast = grammar::CompleteCommandParser::new()
+            .parse(input,lexer)
+            .unwrap();
+        println!("{:#?}", ast);
+        let list = ast.list.unwrap();
+        match &list.0[0].pipeline.pipe_sequence.0[0] {
+            Command::SimpleCommand(command) => {
+                let words = &command.suffix.as_ref().unwrap().word;
+                assert_eq!(words[0], Word(vec![
+                    WordSegment::Literal("a".to_string()),
+                    WordSegment::Escaped('\\'),
+                    WordSegment::DoubleQuoted(vec![parameter("b", None)]),
+                    WordSegment::SingleQuoted("c".to_string()),
+                ]));
+                assert_eq!(words[1], Word(vec![
+                    WordSegment::DoubleQuoted(vec![
+                        parameter("x", Some(ParameterOperation::Length)),
+                        parameter("y", Some(ParameterOperation::Assign(true, Word(vec![WordSegment::DoubleQuoted(vec![WordSegment::Literal("z".to_string())])])))),
+                    ]),
+                    WordSegment::Literal("d".to_string()),
+                    parameter("1", None),
+                    WordSegment::Literal("e".to_string()),
+                    parameter("ns::v", Some(ParameterOperation::RemoveSuffix(true, literal(".*")))),
+                    WordSegment::Arithmetic(Word(vec![
+                        WordSegment::Literal(" ".to_string()),
+                        parameter("n", None),
+                        WordSegment::Literal(" + 1 ".to_string()),
+                    ])),
+                    WordSegment::CommandSubstitution("echo `hi`".to_string()),
+                ]));
+            },
+            _ => panic!("expected a simple command"),
+        }
+    }
+
+    #[test]
+    fn test_bang_pipeline() {
+        let input = "! false | true &\n! true";
+        let lexer = Lexer::new(input);
+        let ast = grammar::CompleteCommandParser::new()
+            .parse(input,lexer)
+            .unwrap();
+        println!("{:#?}", ast);
+        let list = ast.list.unwrap();
+        assert!(list.0[0].pipeline.bang);
+        assert!(list.0[0].pipeline.background);
+        assert_eq!(list.0[0].pipeline.pipe_sequence.0.len(), 2);
+        assert!(list.0[1].pipeline.bang);
+        assert!(!list.0[1].pipeline.background);
+    }
+
+    #[test]
+    fn test_background_separator() {
+        let input = "a && b & c; { d & e & } &";
+        let lexer = Lexer::new(input);
+        let ast = grammar::CompleteCommandParser::new()
+            .parse(input,lexer)
+            .unwrap();
+        println!("{:#?}", ast);
+        let list = ast.list.unwrap();
+        assert_eq!(list.0.len(), 3);
+        assert!(list.0[0].pipeline.background);
+        assert!(!list.0[0].and_or.as_ref().unwrap().pipeline.background);
+        assert!(!list.0[1].pipeline.background);
+        assert!(list.0[2].pipeline.background);
+        match &list.0[2].pipeline.pipe_sequence.0[0] {
+            Command::CompoundCommand(CompoundCommand::BraceGroup(brace_group), None) => {
+                assert!(brace_group.0.0.0.iter().all(|and_or| and_or.pipeline.background));
+            },
+            _ => panic!("expected a brace group"),
+        }
+    }
+
+    #[test]
+    fn test_brace_words() {
+        let input = "{ echo a{b,c}d {1..3}; }";
+        let lexer = Lexer::new(input);
+        let ast = grammar::CompleteCommandParser::new()
+            .parse(input,lexer)
+            .unwrap();
+        println!("{:#?}", ast);
+        let list = ast.list.unwrap();
+        match &list.0[0].pipeline.pipe_sequence.0[0] {
+            Command::CompoundCommand(CompoundCommand::BraceGroup(brace_group), None) => {
+                match &brace_group.0.0.0[0].pipeline.pipe_sequence.0[0] {
+                    Command::SimpleCommand(command) => {
+                        assert_eq!(command.suffix.as_ref().unwrap().word, vec![literal("a{b,c}d"), literal("{1..3}")]);
+                    },
+                    _ => panic!("expected a simple command"),
+                }
+            },
+            _ => panic!("expected a brace group"),
+        }
+    }
+
+    #[test]
+    fn test_process_substitution() {
+        let input = "diff <(sort a) >(cat) \"<(x)\" < b";
+        let lexer = Lexer::new(input);
+        let ast = grammar::CompleteCommandParser::new()
+            .parse(input,lexer)
+            .unwrap();
+        println!("{:#?}", ast);
+        let list = ast.list.unwrap();
+        match &list.0[0].pipeline.pipe_sequence.0[0] {
+            Command::SimpleCommand(command) => {
+                let suffix = command.suffix.as_ref().unwrap();
+                assert_eq!(suffix.word, vec![
+                    Word(vec![WordSegment::ProcessSubstitution(true, "sort a".to_string())]),
+                    Word(vec![WordSegment::ProcessSubstitution(false, "cat".to_string())]),
+                    Word(vec![WordSegment::DoubleQuoted(vec![WordSegment::Literal("<(x)".to_string())])]),
+                ]);
+                assert_eq!(suffix.io_redirect.len(), 1);
+            },
+            _ => panic!("expected a simple command"),
+        }
+    }
+
+    #[test]
+    fn test_array_assignment() {
+        let input = "arr=(a \"b c\"\n  $d) arr[i+1]+=x echo \"${arr[@]}\" ${#arr[*]} ${!arr[@]} ${arr[$i]}";
+        let lexer = Lexer::new(input);
+        let ast = grammar::CompleteCommandParser::new()
+            .parse(input,lexer)
+            .unwrap();
+        println!("{:#?}", ast);
+        let list = ast.list.unwrap();
+        match &list.0[0].pipeline.pipe_sequence.0[0] {
+            Command::SimpleCommand(command) => {
+                assert_eq!(command.prefix.as_ref().unwrap().assignment, vec![
+                    Assignment {
+                        name: "arr".to_string(),
+                        index: None,
+                        append: false,
+                        value: AssignmentValue::Array(vec![
+                            literal("a"),
+                            Word(vec![WordSegment::DoubleQuoted(vec![WordSegment::Literal("b c".to_string())])]),
+                            Word(vec![parameter("d", None)]),
+                        ]),
+                    },
+                    Assignment { name: "arr".to_string(), index: Some(literal("i+1")), append: true, value: AssignmentValue::Scalar(literal("x")) },
+                ]);
+                let array = |subscript: Subscript, operation: Option<ParameterOperation>| {
+                    WordSegment::Parameter(Parameter { name: "arr".to_string(), subscript: Some(subscript), operation })
+                };
+                assert_eq!(command.suffix.as_ref().unwrap().word, vec![
+                    Word(vec![WordSegment::DoubleQuoted(vec![array(Subscript::All, None)])]),
+                    Word(vec![array(Subscript::Joined, Some(ParameterOperation::Length))]),
+                    Word(vec![array(Subscript::All, Some(ParameterOperation::Keys))]),
+                    Word(vec![array(Subscript::Index(Word(vec![parameter("i", None)])), None)]),
+                ]);
+            },
+            _ => panic!("expected a simple command"),
+        }
+    }
+
+    #[test]
+    fn test_conditional_command() {
+        let input = "[[ ! -f $f && ($x == a* || \"$y\" =~ ^(a|b)+$) ]] || echo no";
+        let lexer = Lexer::new(input);
+        let ast = grammar::CompleteCommandParser::new()
+            .parse(input,lexer)
+            .unwrap();
+        println!("{:#?}", ast);
+        let list = ast.list.unwrap();
+        let and_or = list.0[0].and_or.as_ref().unwrap();
+        let word = |name: &str| Word(vec![parameter(name, None)]);
+        assert_eq!(and_or.pipeline.pipe_sequence.0[0], Command::CompoundCommand(CompoundCommand::ConditionalCommand(ConditionalCommand(
+            ConditionalExpression::And(
+                Box::new(ConditionalExpression::Not(Box::new(ConditionalExpression::Unary("-f".to_string(), word("f"))))),
+                Box::new(ConditionalExpression::Or(
+                    Box::new(ConditionalExpression::Binary(word("x"), "==".to_string(), literal("a*"))),
+                    Box::new(ConditionalExpression::Binary(
+                        Word(vec![WordSegment::DoubleQuoted(vec![parameter("y", None)])]),
+                        "=~".to_string(),
+                        literal("^(a|b)+$"),
+                    )),
+                )),
+            ),
+        )), None));
+    }
 }
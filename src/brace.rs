@@ -0,0 +1,185 @@
+use crate::ast::{Word, WordSegment};
+
+/// A piece of a word as brace expansion sees it.
+/// Only the characters of unquoted literals can form a brace expression, everything else is kept as it is.
+#[derive(Debug,Clone,Copy)]
+enum Item<'a> {
+    Char(char),
+    Segment(&'a WordSegment),
+}
+
+/// This function does brace expansion on a word and returns the words that it expands to.
+/// A brace expression is either a comma separated list like `{a,b,c}`, or a sequence like `{1..10}`, `{a..f}`
+/// or `{1..10..2}`, where a number with a leading zero pads all the numbers of the sequence to the same width.
+/// Brace expressions can be nested, and braces that do not form one are left as they are.
+pub fn expand_braces(word: &Word) -> Vec<Word> {
+    let mut items = Vec::new();
+    for segment in word.0.iter() {
+        match segment {
+            WordSegment::Literal(text) => items.extend(text.chars().map(Item::Char)),
+            segment => items.push(Item::Segment(segment)),
+        }
+    }
+    expand_items(&items).iter().map(|items| to_word(items)).collect()
+}
+
+/// This function expands the first brace expression in the items, then the ones in each of the words that it gives.
+fn expand_items<'a>(items: &[Item<'a>]) -> Vec<Vec<Item<'a>>> {
+    let opens = items.iter().enumerate().filter(|(_, item)| matches!(item, Item::Char('{'))).map(|(index, _)| index);
+    for open in opens {
+        let (close, alternatives) = match brace_expression(items, open) {
+            Some(expression) => expression,
+            None => continue,
+        };
+        let mut words = Vec::new();
+        for alternative in alternatives {
+            let mut word = items[..open].to_vec();
+            word.extend(alternative);
+            word.extend_from_slice(&items[close + 1..]);
+            words.append(&mut expand_items(&word));
+        }
+        return words;
+    }
+    vec![items.to_vec()]
+}
+
+/// This function reads the brace expression that starts at the open brace.
+/// It returns the position of the close brace and the alternatives that the expression stands for,
+/// or None if the braces are not closed or what is between them is neither a list nor a sequence.
+fn brace_expression<'a>(items: &[Item<'a>], open: usize) -> Option<(usize, Vec<Vec<Item<'a>>>)> {
+    let mut depth = 0;
+    let mut commas = Vec::new();
+    for (index, item) in items.iter().enumerate().skip(open + 1) {
+        match item {
+            Item::Char('{') => depth += 1,
+            Item::Char('}') if depth > 0 => depth -= 1,
+            Item::Char('}') => {
+                if commas.is_empty() {
+                    return sequence(&items[open + 1..index]).map(|alternatives| (index, alternatives));
+                }
+                let mut alternatives = Vec::new();
+                let mut start = open + 1;
+                for comma in commas.into_iter().chain(std::iter::once(index)) {
+                    alternatives.push(items[start..comma].to_vec());
+                    start = comma + 1;
+                }
+                return Some((index, alternatives));
+            },
+            Item::Char(',') if depth == 0 => commas.push(index),
+            _ => {},
+        }
+    }
+    None
+}
+
+/// This function expands the inside of a sequence expression, `start..end` or `start..end..step`,
+/// where start and end are either both numbers or both single characters.
+fn sequence<'a>(items: &[Item<'a>]) -> Option<Vec<Vec<Item<'a>>>> {
+    let mut text = String::new();
+    for item in items {
+        match item {
+            Item::Char(chr) => text.push(*chr),
+            Item::Segment(_) => return None,
+        }
+    }
+
+    let parts: Vec<&str> = text.split("..").collect();
+    let (start, end, step) = match parts[..] {
+        [start, end] => (start, end, 1),
+        [start, end, step] => (start, end, step.parse::<i64>().ok()?),
+        _ => return None,
+    };
+    let step = step.unsigned_abs().max(1) as usize;
+
+    let values: Vec<String> = match (start.parse::<i64>(), end.parse::<i64>()) {
+        (Ok(first), Ok(last)) => {
+            let width = if is_padded(start) || is_padded(end) { start.len().max(end.len()) } else { 0 };
+            range(first, last, step).map(|value| format!("{:0width$}", value, width = width)).collect()
+        },
+        (Err(_), Err(_)) => {
+            let mut start_chars = start.chars();
+            let mut end_chars = end.chars();
+            match (start_chars.next(), start_chars.next(), end_chars.next(), end_chars.next()) {
+                (Some(first), None, Some(last), None) => {
+                    range(first as i64, last as i64, step)
+                        .filter_map(|value| char::from_u32(value as u32))
+                        .map(String::from)
+                        .collect()
+                },
+                _ => return None,
+            }
+        },
+        _ => return None,
+    };
+    Some(values.into_iter().map(|value| value.chars().map(Item::Char).collect()).collect())
+}
+
+/// This function counts from first to last, up or down, taking steps of the given size.
+fn range(first: i64, last: i64, step: usize) -> Box<dyn Iterator<Item = i64>> {
+    if first <= last {
+        Box::new((first..=last).step_by(step))
+    }
+    else {
+        Box::new((last..=first).rev().step_by(step))
+    }
+}
+
+/// This function checks if a number in a sequence has a leading zero, which makes the sequence zero padded.
+fn is_padded(number: &str) -> bool {
+    let digits = number.trim_start_matches(['-', '+']);
+    digits.len() > 1 && digits.starts_with('0')
+}
+
+/// This function turns the items back into a word, joining characters that follow each other into one literal.
+fn to_word(items: &[Item]) -> Word {
+    let mut segments = Vec::new();
+    for item in items {
+        match item {
+            Item::Char(chr) => {
+                if let Some(WordSegment::Literal(text)) = segments.last_mut() {
+                    text.push(*chr);
+                }
+                else {
+                    segments.push(WordSegment::Literal(chr.to_string()));
+                }
+            },
+            Item::Segment(segment) => segments.push((*segment).clone()),
+        }
+    }
+    Word(segments)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::lexer;
+
+    fn expand(text: &str) -> Vec<String> {
+        expand_braces(&lexer::parse_word(text)).iter().map(|word| {
+            word.0.iter().map(|segment| match segment {
+                WordSegment::Literal(text) => text.clone(),
+                segment => format!("<{:?}>", segment),
+            }).collect()
+        }).collect()
+    }
+
+    #[test]
+    fn test_expand_braces() {
+        assert_eq!(expand("src/{bin,lib,tests}"), vec!["src/bin", "src/lib", "src/tests"]);
+        assert_eq!(expand("a{b,c{d,e}}f"), vec!["abf", "acdf", "acef"]);
+        assert_eq!(expand("{a,b}{1,2}"), vec!["a1", "a2", "b1", "b2"]);
+        assert_eq!(expand("x{,y}"), vec!["x", "xy"]);
+        assert_eq!(expand("{1..5}"), vec!["1", "2", "3", "4", "5"]);
+        assert_eq!(expand("{1..10..4}"), vec!["1", "5", "9"]);
+        assert_eq!(expand("{3..-3..3}"), vec!["3", "0", "-3"]);
+        assert_eq!(expand("{08..11}"), vec!["08", "09", "10", "11"]);
+        assert_eq!(expand("{a..e..2}"), vec!["a", "c", "e"]);
+        assert_eq!(expand("{c..a}"), vec!["c", "b", "a"]);
+        assert_eq!(expand("{a}{b,c}"), vec!["{a}b", "{a}c"]);
+        assert_eq!(expand("{a,b"), vec!["{a,b"]);
+        assert_eq!(expand("{1..b}"), vec!["{1..b}"]);
+        assert_eq!(expand("{}"), vec!["{}"]);
+        assert_eq!(expand("'{a,b}'"), vec!["<SingleQuoted(\"{a,b}\")>"]);
+        assert_eq!(expand("\\{a,b}"), vec!["<Escaped('{')>a,b}"]);
+    }
+}
@@ -4,7 +4,7 @@ use std::fs::File;
 use std::io::prelude::*;
 use std::process::exit;
 use std::io::Write;
-use crate::ast::SimpleCommand;
+use crate::ast::{Assignment, AssignmentValue, SimpleCommand};
 use crate::shell;
 use nix::unistd::Pid;
 use nix::sys::signal::kill;
@@ -13,10 +13,13 @@ use std::ffi::CString;
 use crate::jobs;
 use crate::trap;
 use crate::eval;
+use crate::expand;
+use crate::condition;
 use crate::log;
 use crate::context::ContextUtils;
-use crate::context::Context;
+use crate::context::{Context, Var};
 use crate::jobs::Process;
+use crate::process::CommandExitStatus;
 
 use std::rc::Rc;
 use std::cell::RefCell;
@@ -24,7 +27,7 @@ use std::cell::RefCell;
 use lalrpop_util::lalrpop_mod;
 lalrpop_mod!(pub grammar);
 
-use crate::lexer::Lexer;
+use crate::lexer::{self, Lexer};
 
 enum IdType {
     Pid,
@@ -46,26 +49,39 @@ fn trim(word: &str) -> String {
 
 /// This function is the 'cd' command of the shell.
 /// We use the env::set_current_dir function to change the current directory.
-/// this needs to change several variables when changing but for now we won't care
-pub fn change_directory(command: &SimpleCommand) -> Result<(), std::io::Error> {
+/// PWD and OLDPWD are updated afterwards so that `~+` and `~-` refer to the new and the old directory.
+pub fn change_directory(command: &SimpleCommand<String>) -> Result<(), std::io::Error> {
     
     trap::interrupts_off();
     let path;
-    if command.suffix.is_none() {
+    if command.suffix.is_none() || command.suffix.as_ref().unwrap().word.is_empty() {
         path = env::var("HOME").unwrap();
     } else {
         path = command.suffix.as_ref().unwrap().word[0].to_string();
     }
+    let old_directory = env::current_dir();
     env::set_current_dir(path)?;
+    if let Ok(old_directory) = old_directory {
+        set_directory_var("OLDPWD", &old_directory.to_string_lossy());
+    }
+    if let Ok(directory) = env::current_dir() {
+        set_directory_var("PWD", &directory.to_string_lossy());
+    }
     trap::interrupts_on();
     Ok(())
 }
 
+/// This is an internal function that sets a variable that holds a directory for the shell and its children.
+fn set_directory_var(name: &str, directory: &str) {
+    env::set_var(name, directory);
+    shell::add_var_context(&format!("{}={}", name, directory));
+}
+
 /// This is the 'exit' command of the shell.
 /// Despite having a return value, this function will cause the shell to exit.
 /// By default it will exit with a status code of 0, but if the user specifies a status code
 /// it will exit with that status code.
-pub fn quit(command: &SimpleCommand) -> Result<(), std::io::Error> {
+pub fn quit(command: &SimpleCommand<String>) -> Result<(), std::io::Error> {
     shell::save_history();
     if command.suffix.is_none() || command.suffix.as_ref().unwrap().word.is_empty() {
         let exit_code = eval::get_exit_code();
@@ -79,21 +95,134 @@ pub fn quit(command: &SimpleCommand) -> Result<(), std::io::Error> {
 }
 
 /// This is the 'return' command of the shell.
-/// It returns from a function.
+/// It returns from a function or a sourced file, which the evaluator unwinds to like a break.
 /// By default, it returns the last command's exit status.
 /// It takes a SimpleCommand with a suffix that is a string of the form 'number'.
-pub fn return_cmd(command: &SimpleCommand) -> Result<(), std::io::Error> {
-    if command.suffix.is_none() || command.suffix.as_ref().unwrap().word.is_empty() {
+pub fn return_cmd(command: &SimpleCommand<String>) -> Result<(), std::io::Error> {
+    shell::set_loop_skip(CommandExitStatus::Return, 1);
+    let word = match command.suffix.as_ref().and_then(|suffix| suffix.word.first()) {
+        Some(word) => word,
+        None => return Ok(()),
+    };
+    match word.parse::<i32>() {
+        Ok(code) => {
+            eval::set_exit_status(code);
+            Ok(())
+        },
+        Err(_) => Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, format!("return: {}: numeric argument required", word))),
+    }
+}
+
+/// This is the 'break' command of the shell.
+/// It exits from the enclosing loop, or from the n enclosing loops if given a number n.
+pub fn break_cmd(command: &SimpleCommand<String>) -> Result<(), std::io::Error> {
+    loop_skip(command, CommandExitStatus::Break)
+}
+
+/// This is the 'continue' command of the shell.
+/// It starts the next iteration of the enclosing loop, or of the nth enclosing loop if given a number n.
+pub fn continue_cmd(command: &SimpleCommand<String>) -> Result<(), std::io::Error> {
+    loop_skip(command, CommandExitStatus::Continue)
+}
+
+/// This is an internal function that records a break or continue for the evaluator to unwind.
+/// If the count is larger than the number of enclosing loops, all of them are unwound.
+fn loop_skip(command: &SimpleCommand<String>, skip: CommandExitStatus) -> Result<(), std::io::Error> {
+    let depth = shell::get_loop_depth();
+    if depth == 0 {
+        return Err(std::io::Error::new(std::io::ErrorKind::Other, format!("{}: only meaningful in a loop", command.name)));
+    }
+
+    let count = match command.suffix.as_ref().and_then(|suffix| suffix.word.first()) {
+        None => 1,
+        Some(word) => match word.parse::<usize>() {
+            Ok(count) if count > 0 => count,
+            _ => {
+                return Err(std::io::Error::new(std::io::ErrorKind::Other, format!("{}: {}: loop count out of range", command.name, word)));
+            }
+        },
+    };
+
+    shell::set_loop_skip(skip, count.min(depth));
+    Ok(())
+}
+
+/// The options that set turns on with `-o name` and off with `+o name`.
+const SET_OPTIONS: &[&str] = &["noglob", "pipefail"];
+/// The options that shopt turns on with `-s name` and off with `-u name`.
+const SHOPT_OPTIONS: &[&str] = &["dotglob", "nullglob"];
+
+/// This is the 'set' command of the shell.
+/// It turns shell options on with `-o name` and off with `+o name`.
+/// `-f` and `+f` are short for noglob. Without a name after `-o` or `+o`, or without any arguments, it lists the options.
+pub fn set(command: &SimpleCommand<String>) -> Result<(), std::io::Error> {
+    let words = command.suffix.as_ref().map(|suffix| suffix.word.clone()).unwrap_or_default();
+    if words.is_empty() {
+        print_options(SET_OPTIONS);
         return Ok(());
     }
-    //let chars = command.suffix.as_ref().unwrap().word[0].chars();
-    //chars.next();
-    let code = command.suffix.as_ref().unwrap().word[0].parse::<i32>().unwrap();
 
-    eval::set_exit_status(code);
+    let mut words = words.iter();
+    while let Some(word) = words.next() {
+        let on = word.starts_with('-');
+        match word.as_str() {
+            "-f" | "+f" => shell::set_option("noglob", on),
+            "-o" | "+o" => {
+                match words.next() {
+                    Some(name) if SET_OPTIONS.contains(&name.as_str()) => shell::set_option(name, on),
+                    Some(name) => {
+                        return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, format!("set: {}: invalid option name", name)));
+                    },
+                    None => print_options(SET_OPTIONS),
+                }
+            },
+            _ => {
+                return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, format!("set: {}: invalid option", word)));
+            },
+        }
+    }
     Ok(())
 }
 
+/// This is the 'shopt' command of the shell.
+/// It turns the named options on with `-s` and off with `-u`.
+/// Without a flag it prints the state of the named options, or of all of them if none are named.
+pub fn shopt(command: &SimpleCommand<String>) -> Result<(), std::io::Error> {
+    let words = command.suffix.as_ref().map(|suffix| suffix.word.clone()).unwrap_or_default();
+    let (on, names) = match words.first().map(|word| word.as_str()) {
+        Some("-s") => (Some(true), &words[1..]),
+        Some("-u") => (Some(false), &words[1..]),
+        Some(word) if word.starts_with('-') => {
+            return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, format!("shopt: {}: invalid option", word)));
+        },
+        _ => (None, &words[..]),
+    };
+
+    if let Some(name) = names.iter().find(|name| !SHOPT_OPTIONS.contains(&name.as_str())) {
+        return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, format!("shopt: {}: invalid shell option name", name)));
+    }
+    match on {
+        Some(on) if !names.is_empty() => {
+            for name in names {
+                shell::set_option(name, on);
+            }
+        },
+        _ if names.is_empty() => print_options(SHOPT_OPTIONS),
+        _ => {
+            let names: Vec<&str> = names.iter().map(|name| name.as_str()).collect();
+            print_options(&names);
+        },
+    }
+    Ok(())
+}
+
+/// This is an internal function that prints whether each of the options is on or off.
+fn print_options(options: &[&str]) {
+    for option in options {
+        println!("{:<15}\t{}", option, if shell::get_option(option) { "on" } else { "off" });
+    }
+}
+
 /// This is the 'jobs' command of the shell.
 /// It prints out all of the jobs that are currently running.
 pub fn jobs() -> Result<(), std::io::Error> {
@@ -105,7 +234,7 @@ pub fn jobs() -> Result<(), std::io::Error> {
 /// This is the 'fg' and 'bg' commands of the shell.
 /// They are used to bring a job to the foreground or background respectively.
 /// They take a SimpleCommand with a suffix that is either a valid Pid or a job id if it starts with a '%'.
-pub fn fgbg(command: &SimpleCommand) -> Result<(), std::io::Error> {
+pub fn fgbg(command: &SimpleCommand<String>) -> Result<(), std::io::Error> {
     trap::interrupts_off();
     let id;
     let id_type;
@@ -205,7 +334,7 @@ fn bg(id: usize, id_type: IdType) -> Result<(), std::io::Error> {
 /// It takes a SimpleCommand with a suffix that is a string of the form 'alias=command'.
 /// If the suffix is empty, it will print out all of the current aliases.
 /// If the suffix is '-p', it will print out all of the current aliases.
-pub fn alias(command: &SimpleCommand) -> Result<(), std::io::Error> {
+pub fn alias(command: &SimpleCommand<String>) -> Result<(), std::io::Error> {
     if command.suffix.is_none() {
         shell::display_aliases();
         return Ok(());
@@ -229,7 +358,7 @@ pub fn alias(command: &SimpleCommand) -> Result<(), std::io::Error> {
 /// It is used to remove aliases for commands.
 /// It takes a SimpleCommand with a suffix that is a string of the form 'alias'.
 /// If the suffix is '-a', it will remove all of the current aliases.
-pub fn unalias(command: &SimpleCommand) -> Result<(), std::io::Error> {
+pub fn unalias(command: &SimpleCommand<String>) -> Result<(), std::io::Error> {
     if command.suffix.is_none() {
         return Err(std::io::Error::new(std::io::ErrorKind::Other, "unalias needs an argument"));
     }
@@ -287,7 +416,7 @@ fn create_context_from_file(file: &str) -> Result<Rc<RefCell<Context>>, std::io:
 /// .    If the second value contains an equal sign ('='), with the left side being the namespace and the right side either being a file or 'self'.
 /// .        If the right side is a file, it will evaluate the as a new context and export it.
 /// .        If the right side is 'self', it will export the current context to the environment using the namespace defined by the left side.
-pub fn export(command: &SimpleCommand) -> Result<(), std::io::Error> {
+pub fn export(command: &SimpleCommand<String>) -> Result<(), std::io::Error> {
     if (command.suffix.is_none() || command.suffix.as_ref().unwrap().word.len() == 0) || command.suffix.as_ref().unwrap().word[0].contains("-p") {
         env::vars().for_each(|(key, value)| {
             println!("{}={}", key, value);
@@ -321,7 +450,7 @@ pub fn export(command: &SimpleCommand) -> Result<(), std::io::Error> {
             if suffix.word[1].as_str() == "self" {
                 let context = shell::get_current_context();
                 let namespace = {
-                    context.borrow().get_var("0").unwrap().borrow().value.clone()
+                    context.borrow().get_var("0").unwrap().borrow().scalar().to_string()
                 };
                 shell::add_context(&namespace, context);
                 return Ok(());
@@ -336,6 +465,10 @@ pub fn export(command: &SimpleCommand) -> Result<(), std::io::Error> {
     
     for word in command.suffix.as_ref().unwrap().word.iter() {
         if !word.contains('=') {
+            // the environment only holds strings
+            if shell::get_var(word).is_some_and(|var| var.is_array()) {
+                return Err(std::io::Error::new(std::io::ErrorKind::Other, format!("export: {}: cannot export an array", word)));
+            }
             let var = shell::expand_var(word);
             if var.is_none() {
                 let function = shell::get_function(word);
@@ -359,12 +492,29 @@ pub fn export(command: &SimpleCommand) -> Result<(), std::io::Error> {
     Ok(())
 }
 
-/// This evaluates an assignment which is a SimpleCommand with a prefix that is a string of the form 'variable=value'.
-pub fn assignment(command: &SimpleCommand) -> Result<(), std::io::Error> {
-
-
+/// This evaluates an assignment which is a SimpleCommand with a prefix of assignments and no command name.
+pub fn assignment(command: &SimpleCommand<String>) -> Result<(), std::io::Error> {
+    for assignment in command.prefix.as_ref().unwrap().assignment.iter() {
+        assign(assignment).map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))?;
+    }
+    Ok(())
+}
 
-    shell::add_var_context(command.prefix.as_ref().unwrap().assignment[0].as_str());
+/// This function sets a variable, an array or an element of an array to the value of an assignment.
+/// With `+=` the value is appended to the variable or the element, and the elements to the array.
+fn assign(assignment: &Assignment<String>) -> Result<(), String> {
+    let name = assignment.name.as_str();
+    match (&assignment.index, &assignment.value) {
+        (None, AssignmentValue::Array(values)) => shell::set_array(name, values.clone(), assignment.append),
+        (Some(index), AssignmentValue::Scalar(value)) => {
+            shell::set_array_element(name, &expand::array_key(name, index)?, value, assignment.append);
+        },
+        (Some(_), AssignmentValue::Array(_)) => return Err(format!("{}: cannot assign a list to an array element", name)),
+        (None, AssignmentValue::Scalar(value)) => {
+            let old = if assignment.append { shell::expand_var(name).unwrap_or_default() } else { String::new() };
+            shell::add_var_context(&format!("{}={}{}", name, old, value));
+        },
+    }
     Ok(())
 }
 
@@ -372,7 +522,7 @@ pub fn assignment(command: &SimpleCommand) -> Result<(), std::io::Error> {
 /// This is the 'eval' command of the shell.
 /// It evaluates a string as a command.
 /// It takes a SimpleCommand with a suffix that is a string of the form 'string'.
-pub fn eval_cmd(command: &SimpleCommand) -> Result<(), std::io::Error> {
+pub fn eval_cmd(command: &SimpleCommand<String>) -> Result<(), std::io::Error> {
     if command.suffix.is_none() {
         return Err(std::io::Error::new(std::io::ErrorKind::Other, "eval needs an argument"));
     }
@@ -400,6 +550,32 @@ pub fn eval_cmd(command: &SimpleCommand) -> Result<(), std::io::Error> {
     Ok(())
 }
 
+/// This is the 'test' command of the shell, which is the '[' command as well when its last argument is `]`.
+/// The exit status is 0 if the expression is true, 1 if it is false and 2 if it is not a valid expression,
+/// which is reported without stopping the commands that follow it.
+pub fn test(command: &SimpleCommand<String>) -> Result<(), std::io::Error> {
+    let mut args: Vec<&str> = match &command.suffix {
+        Some(suffix) => suffix.word.iter().map(String::as_str).collect(),
+        None => Vec::new(),
+    };
+    let result = if command.name == "[" && args.pop() != Some("]") {
+        Err("missing `]'".to_string())
+    }
+    else {
+        condition::evaluate(&args)
+    };
+    let status = match result {
+        Ok(true) => 0,
+        Ok(false) => 1,
+        Err(err) => {
+            eprintln!("rsh: {}: {}", command.name, err);
+            2
+        },
+    };
+    eval::set_exit_status(status);
+    Ok(())
+}
+
 /// This is the 'pwd' command of the shell.
 /// It prints out the current working directory.
 pub fn pwd() -> Result<(), std::io::Error> {
@@ -414,7 +590,99 @@ enum Flags {
     Context,
 }
 
-pub fn unset(command: &SimpleCommand) -> Result<(), std::io::Error> {
+/// This is the 'declare' command of the shell.
+/// `declare -a name` makes a variable an indexed array, `declare -A name` an associative array
+/// and `declare -r name` makes it readonly.
+/// A name can be given a value as in `declare name=value` or `declare -A name[key]=value`.
+/// Inside a function the variables are local to it, unless `-g` makes them global.
+pub fn declare(command: &SimpleCommand<String>) -> Result<(), std::io::Error> {
+    declare_vars("declare", command)
+}
+
+/// This is the 'local' command of the shell.
+/// It declares variables that are local to the function that is running, which shadow the variables of the same
+/// name for as long as the function runs, in the functions that it calls as well.
+/// It takes the same options as `declare` apart from `-g`.
+pub fn local(command: &SimpleCommand<String>) -> Result<(), std::io::Error> {
+    if !shell::in_function() {
+        return Err(std::io::Error::new(std::io::ErrorKind::Other, "local: can only be used in a function"));
+    }
+    declare_vars("local", command)
+}
+
+/// This function declares the variables of a `declare` or `local` command.
+fn declare_vars(builtin: &str, command: &SimpleCommand<String>) -> Result<(), std::io::Error> {
+    let error = |message: String| std::io::Error::new(std::io::ErrorKind::Other, message);
+    let words = match &command.suffix {
+        Some(suffix) if !suffix.word.is_empty() => &suffix.word,
+        _ => return Err(error(format!("{} needs an argument", builtin))),
+    };
+
+    let mut associative = None;
+    let mut readonly = false;
+    let mut global = false;
+    let mut pos = 0;
+    while let Some(options) = words.get(pos).and_then(|word| word.strip_prefix('-')) {
+        for option in options.chars() {
+            match option {
+                'a' => associative = Some(false),
+                'A' => associative = Some(true),
+                'r' => readonly = true,
+                'g' if builtin == "declare" => global = true,
+                _ => return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, format!("{}: -{}: invalid option", builtin, option))),
+            }
+        }
+        pos += 1;
+    }
+    let local = shell::in_function() && !global;
+
+    for word in words[pos..].iter() {
+        let assignment = lexer::split_assignment(word).map(|(name, index, append, value)| Assignment {
+            name: name.to_string(),
+            index: index.map(str::to_string),
+            append,
+            value: AssignmentValue::Scalar(value.to_string()),
+        });
+        let name = assignment.as_ref().map_or(word.as_str(), |assignment| assignment.name.as_str());
+        if !name.split("::").all(lexer::is_name) || (local && name.contains("::")) {
+            return Err(error(format!("{}: `{}': not a valid identifier", builtin, word)));
+        }
+        if local {
+            let var = match associative {
+                Some(associative) => {
+                    let mut var = Var::new_array(name, Vec::new());
+                    var.make_array(associative);
+                    var
+                },
+                None => Var::new(name, ""),
+            };
+            shell::add_var_local(var);
+        }
+        if let Some(associative) = associative {
+            match shell::get_var(name) {
+                Some(var) if var.is_associative() && !associative => {
+                    return Err(error(format!("{}: {}: cannot convert associative to indexed array", builtin, name)));
+                },
+                Some(var) if var.is_array() && !var.is_associative() && associative => {
+                    return Err(error(format!("{}: {}: cannot convert indexed to associative array", builtin, name)));
+                },
+                _ => shell::declare_array(name, associative),
+            }
+        }
+        if let Some(assignment) = &assignment {
+            assign(assignment).map_err(|e| error(format!("{}: {}", builtin, e)))?;
+        }
+        if readonly {
+            if shell::get_var(name).is_none() {
+                shell::add_var_context(&format!("{}=", name));
+            }
+            shell::set_readonly_var(name);
+        }
+    }
+    Ok(())
+}
+
+pub fn unset(command: &SimpleCommand<String>) -> Result<(), std::io::Error> {
     if command.suffix.is_none() || command.suffix.as_ref().unwrap().word.len() == 0 {
 	return Err(std::io::Error::new(std::io::ErrorKind::Other, "unset needs an argument"));
     }
@@ -435,13 +703,22 @@ pub fn unset(command: &SimpleCommand) -> Result<(), std::io::Error> {
 	    pos = 1;
 	}
 	else {
-	    return Err(std::io::Error::new(std::io::ErrorKind::Other, "unset: invalid option"));
+	    return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, "unset: invalid option"));
 	}
     }
 
     match flags {
         Flags::Variable => {
-            shell::remove_var(command.suffix.as_ref().unwrap().word[pos].as_str());
+            let name = command.suffix.as_ref().unwrap().word[pos].as_str();
+            // `unset name[subscript]` removes a single element of an array
+            match name.strip_suffix(']').and_then(|name| name.split_once('[')) {
+                Some((array, "@" | "*")) => shell::remove_var(array),
+                Some((array, subscript)) => {
+                    let key = expand::array_key(array, subscript).map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, format!("unset: {}", e)))?;
+                    shell::remove_array_element(array, &key);
+                },
+                None => shell::remove_var(name),
+            }
         },
         Flags::Function => {
             shell::remove_function(command.suffix.as_ref().unwrap().word[pos].as_str());
@@ -456,7 +733,7 @@ pub fn unset(command: &SimpleCommand) -> Result<(), std::io::Error> {
 
 }
 
-pub fn readonly(command: &SimpleCommand) -> Result<(), std::io::Error> {
+pub fn readonly(command: &SimpleCommand<String>) -> Result<(), std::io::Error> {
     if command.suffix.is_none() || command.suffix.as_ref().unwrap().word.len() == 0 {
     return Err(std::io::Error::new(std::io::ErrorKind::Other, "readonly needs an argument"));
     }
@@ -482,7 +759,7 @@ pub fn readonly(command: &SimpleCommand) -> Result<(), std::io::Error> {
             pos = 1;
         }
         else {
-            return Err(std::io::Error::new(std::io::ErrorKind::Other, "readonly: invalid option"));
+            return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, "readonly: invalid option"));
         }
     }
     if command.suffix.as_ref().unwrap().word[pos].as_str().contains('-') {
@@ -491,7 +768,7 @@ pub fn readonly(command: &SimpleCommand) -> Result<(), std::io::Error> {
             pos += 1;
         }
         else {
-            return Err(std::io::Error::new(std::io::ErrorKind::Other, "readonly: invalid option"));
+            return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, "readonly: invalid option"));
         }
     }
 
@@ -543,7 +820,7 @@ pub fn readonly(command: &SimpleCommand) -> Result<(), std::io::Error> {
 
 }
 
-pub fn exec_cmd(command: &SimpleCommand) -> Result<(),std::io::Error> {
+pub fn exec_cmd(command: &SimpleCommand<String>) -> Result<(),std::io::Error> {
 
     if command.suffix.is_none() || command.suffix.as_ref().unwrap().word.len() < 1 {
         return Err(std::io::Error::new(std::io::ErrorKind::Other, "exec needs an argument"));
@@ -645,7 +922,7 @@ pub fn exec_cmd(command: &SimpleCommand) -> Result<(),std::io::Error> {
     Ok(())
 }
 
-pub fn source(command: &SimpleCommand) -> Result<(),std::io::Error> {
+pub fn source(command: &SimpleCommand<String>) -> Result<(),std::io::Error> {
 
     if command.suffix.is_none() || command.suffix.as_ref().unwrap().word.len() < 1 {
         return Err(std::io::Error::new(std::io::ErrorKind::Other, "source needs an argument"));
@@ -698,7 +975,12 @@ pub fn source(command: &SimpleCommand) -> Result<(),std::io::Error> {
     };
 
     log!("AST: {:?}", ast);
-    match eval::eval(&mut ast) {
+    let result = eval::eval(&mut ast);
+    // a return in the file only leaves the file
+    if matches!(shell::get_loop_skip(), Some((CommandExitStatus::Return, _))) {
+        shell::clear_loop_skip();
+    }
+    match result {
         Ok(_) => {},
         Err(e) => {
             return Err(std::io::Error::new(std::io::ErrorKind::Other, format!("Error evaluating string: {}", e)));
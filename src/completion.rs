@@ -1,13 +1,15 @@
 use std::fs;
 use std::env;
 use crate::shell;
+use crate::ast::grammar;
+use crate::lexer::{self, Lexer, Token};
+use lalrpop_util::ParseError;
 
 use std::borrow::Cow::{self, Borrowed, Owned};
 
 use rustyline::completion::FilenameCompleter;
 use rustyline::highlight::{Highlighter, MatchingBracketHighlighter};
 use rustyline::hint::HistoryHinter;
-use rustyline::validate::MatchingBracketValidator;
 use rustyline::{Completer, Helper, Hinter, Validator};
 
 use rustyline::{Context,Result};
@@ -15,13 +17,13 @@ use rustyline::completion::{Pair, Completer};
 use rustyline::completion::Candidate;
 
 
+/// The helper accepts every line as it is entered.
+/// Input that needs more lines is continued by the interactive loop, which prompts with `$PS2` (see `is_incomplete`).
 #[derive(Helper, Completer, Hinter, Validator)]
 pub struct CompletionHelper {
     #[rustyline(Completer)]
     completer: PathCompleter,
     highlighter: MatchingBracketHighlighter,
-    #[rustyline(Validator)]
-    validator: MatchingBracketValidator,
     #[rustyline(Hinter)]
     hinter: HistoryHinter,
 }
@@ -53,13 +55,27 @@ impl Default for CompletionHelper {
         Self {
             completer: PathCompleter::new(),//FilenameCompleter::new(),
             highlighter: MatchingBracketHighlighter::new(),
-            validator: MatchingBracketValidator::new(),
             hinter: HistoryHinter {},
         }
     }
 }
 
 
+/// This function runs the lexer and the grammar over the input to tell if it needs more lines,
+/// which it does if it ends inside a quote, after a backslash or an operator such as `&&` or `|`,
+/// before the delimiter of a here-document or before a compound command is closed.
+/// Input that can never be parsed is not incomplete, so that its error is reported right away.
+pub fn is_incomplete(input: &str) -> bool {
+    let mut lexer = Lexer::new(input);
+    match grammar::CompleteCommandParser::new().parse(input, &mut lexer) {
+        Ok(_) => lexer.is_incomplete(),
+        Err(ParseError::UnrecognizedEOF { .. }) => true,
+        Err(ParseError::UnrecognizedToken { token: (_, Token::EOF, _), .. }) => true,
+        Err(ParseError::User { error: lexer::Error::Unterminated(..) }) => true,
+        Err(_) => false,
+    }
+}
+
 pub struct PathCompleter;
 
 impl Default for PathCompleter {
@@ -126,3 +142,19 @@ fn command_complete(line: &str) -> Vec<Pair> {
     entries.iter().map(|name| Pair { display: name.clone(), replacement: name.to_string()}).collect()
 }
 
+
+#[cfg(test)]
+mod test {
+    use super::is_incomplete;
+
+    #[test]
+    fn test_is_incomplete() {
+        for input in ["echo 'a", "echo \"a", "echo a \\", "true &&", "ls |", "{ echo a", "if true; then",
+            "echo $(ls", "echo ${a", "cat <<EOF\nline", "echo a \\\n", "echo a\\\n"] {
+            assert!(is_incomplete(input), "{}", input);
+        }
+        for input in ["echo 'a'", "echo a \\\nb", "true && false", "{ echo a; }", "cat <<EOF\nline\nEOF", "echo )", "fi", "echo a \\\\\n"] {
+            assert!(!is_incomplete(input), "{}", input);
+        }
+    }
+}
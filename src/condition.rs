@@ -0,0 +1,233 @@
+use std::fs::{self, Metadata};
+use std::os::unix::fs::{FileTypeExt, MetadataExt};
+use nix::unistd::{self, AccessFlags};
+use crate::shell;
+
+/// The operators that test a file, a string, a variable or a shell option, which the `test` builtin and `[[ ]]` share.
+pub const UNARY_OPERATORS: [&str; 26] = [
+    "-a", "-b", "-c", "-d", "-e", "-f", "-g", "-h", "-k", "-p", "-r", "-s", "-t",
+    "-u", "-w", "-x", "-G", "-L", "-N", "-O", "-S", "-z", "-n", "-o", "-v", "-R",
+];
+
+/// The operators that compare two strings, integers or files, which the `test` builtin and `[[ ]]` share.
+pub const BINARY_OPERATORS: [&str; 14] = [
+    "=", "==", "!=", "<", ">", "-eq", "-ne", "-lt", "-le", "-gt", "-ge", "-nt", "-ot", "-ef",
+];
+
+/// The operators of BINARY_OPERATORS that compare integers.
+pub const INTEGER_OPERATORS: [&str; 6] = ["-eq", "-ne", "-lt", "-le", "-gt", "-ge"];
+
+/// This function checks if a word is one of UNARY_OPERATORS.
+pub fn is_unary_operator(operator: &str) -> bool {
+    UNARY_OPERATORS.contains(&operator)
+}
+
+/// This function checks if a word is one of BINARY_OPERATORS.
+pub fn is_binary_operator(operator: &str) -> bool {
+    BINARY_OPERATORS.contains(&operator)
+}
+
+/// This function evaluates the arguments of the `test` builtin, or of `[` without its closing `]`.
+/// Up to four arguments are told apart by how many there are the way POSIX lays down, so that `test -n`
+/// tests if `-n` is empty and `test ! = x` compares `!` to `x`. Longer expressions are parsed
+/// with `!`, `-a`, `-o` and parentheses, where `-a` binds more tightly than `-o`.
+pub fn evaluate(args: &[&str]) -> Result<bool,String> {
+    match args {
+        [] => Ok(false),
+        [word] => Ok(!word.is_empty()),
+        [lhs, operator, rhs] if is_binary_operator(operator) => binary_test(lhs, operator, rhs),
+        ["!", rest @ ..] if args.len() <= 4 => evaluate(rest).map(|result| !result),
+        ["(", inner @ .., ")"] if args.len() <= 4 => evaluate(inner),
+        [operator, operand] if is_unary_operator(operator) => unary_test(operator, operand),
+        [operator, _] => Err(format!("{}: unary operator expected", operator)),
+        _ => {
+            let mut parser = TestParser { args, pos: 0 };
+            let result = parser.or()?;
+            match args.get(parser.pos) {
+                Some(_) => Err("too many arguments".to_string()),
+                None => Ok(result),
+            }
+        },
+    }
+}
+
+/// This struct parses and evaluates the arguments of a `test` builtin with more than four of them.
+struct TestParser<'a> {
+    args: &'a [&'a str],
+    pos: usize,
+}
+
+impl TestParser<'_> {
+    fn peek(&self) -> Option<&str> {
+        self.args.get(self.pos).copied()
+    }
+
+    fn or(&mut self) -> Result<bool,String> {
+        let mut result = self.and()?;
+        while self.peek() == Some("-o") {
+            self.pos += 1;
+            result |= self.and()?;
+        }
+        Ok(result)
+    }
+
+    fn and(&mut self) -> Result<bool,String> {
+        let mut result = self.not()?;
+        while self.peek() == Some("-a") {
+            self.pos += 1;
+            result &= self.not()?;
+        }
+        Ok(result)
+    }
+
+    fn not(&mut self) -> Result<bool,String> {
+        if self.peek() == Some("!") {
+            self.pos += 1;
+            return self.not().map(|result| !result);
+        }
+        self.primary()
+    }
+
+    fn primary(&mut self) -> Result<bool,String> {
+        let args = self.args;
+        let pos = self.pos;
+        match &args[pos..] {
+            [] => Err("argument expected".to_string()),
+            ["(", ..] => {
+                self.pos += 1;
+                let result = self.or()?;
+                if self.peek() != Some(")") {
+                    return Err("`)' expected".to_string());
+                }
+                self.pos += 1;
+                Ok(result)
+            },
+            [lhs, operator, rhs, ..] if is_binary_operator(operator) => {
+                self.pos += 3;
+                binary_test(lhs, operator, rhs)
+            },
+            [operator, operand, ..] if is_unary_operator(operator) => {
+                self.pos += 2;
+                unary_test(operator, operand)
+            },
+            [word, ..] => {
+                self.pos += 1;
+                Ok(!word.is_empty())
+            },
+        }
+    }
+}
+
+/// This function tests a file, a string, a variable or a shell option with one of UNARY_OPERATORS.
+/// The file tests follow symbolic links, except for `-h` and `-L` that test if the file is one,
+/// and are false for a file that does not exist.
+pub fn unary_test(operator: &str, operand: &str) -> Result<bool,String> {
+    let metadata = || fs::metadata(operand).ok();
+    let mode = |bits: u32| metadata().is_some_and(|metadata| metadata.mode() & bits != 0);
+    let access = |flags: AccessFlags| unistd::access(operand, flags).is_ok();
+    let file = |test: fn(&Metadata) -> bool| metadata().is_some_and(|metadata| test(&metadata));
+
+    Ok(match operator {
+        "-a" | "-e" => metadata().is_some(),
+        "-b" => file(|metadata| metadata.file_type().is_block_device()),
+        "-c" => file(|metadata| metadata.file_type().is_char_device()),
+        "-d" => file(Metadata::is_dir),
+        "-f" => file(Metadata::is_file),
+        "-p" => file(|metadata| metadata.file_type().is_fifo()),
+        "-S" => file(|metadata| metadata.file_type().is_socket()),
+        "-h" | "-L" => fs::symlink_metadata(operand).is_ok_and(|metadata| metadata.file_type().is_symlink()),
+        "-g" => mode(0o2000),
+        "-u" => mode(0o4000),
+        "-k" => mode(0o1000),
+        "-r" => access(AccessFlags::R_OK),
+        "-w" => access(AccessFlags::W_OK),
+        "-x" => access(AccessFlags::X_OK),
+        "-s" => file(|metadata| metadata.len() > 0),
+        "-G" => file(|metadata| metadata.gid() == unistd::getegid().as_raw()),
+        "-O" => file(|metadata| metadata.uid() == unistd::geteuid().as_raw()),
+        "-N" => file(|metadata| metadata.mtime() > metadata.atime()),
+        "-t" => operand.trim().parse::<i32>().is_ok_and(|fd| unistd::isatty(fd).unwrap_or(false)),
+        "-z" => operand.is_empty(),
+        "-n" => !operand.is_empty(),
+        "-o" => shell::get_option(operand),
+        "-v" => shell::get_var(operand).is_some(),
+        // there are no namerefs, so no variable is one
+        "-R" => false,
+        operator => return Err(format!("{}: unary operator expected", operator)),
+    })
+}
+
+/// This function compares two strings, integers or files with one of BINARY_OPERATORS.
+/// Strings are compared as they are, `<` and `>` sorting them by their bytes.
+pub fn binary_test(lhs: &str, operator: &str, rhs: &str) -> Result<bool,String> {
+    Ok(match operator {
+        "=" | "==" => lhs == rhs,
+        "!=" => lhs != rhs,
+        "<" => lhs < rhs,
+        ">" => lhs > rhs,
+        // a file that exists is newer than one that does not
+        "-nt" => match (modified(lhs), modified(rhs)) {
+            (Some(lhs), Some(rhs)) => lhs > rhs,
+            (lhs, rhs) => lhs.is_some() && rhs.is_none(),
+        },
+        "-ot" => match (modified(lhs), modified(rhs)) {
+            (Some(lhs), Some(rhs)) => lhs < rhs,
+            (lhs, rhs) => lhs.is_none() && rhs.is_some(),
+        },
+        "-ef" => match (fs::metadata(lhs), fs::metadata(rhs)) {
+            (Ok(lhs), Ok(rhs)) => lhs.dev() == rhs.dev() && lhs.ino() == rhs.ino(),
+            _ => false,
+        },
+        operator if INTEGER_OPERATORS.contains(&operator) => compare(integer(lhs)?, operator, integer(rhs)?),
+        operator => return Err(format!("{}: binary operator expected", operator)),
+    })
+}
+
+/// This function compares two integers with one of INTEGER_OPERATORS.
+pub fn compare(lhs: i64, operator: &str, rhs: i64) -> bool {
+    match operator {
+        "-eq" => lhs == rhs,
+        "-ne" => lhs != rhs,
+        "-lt" => lhs < rhs,
+        "-le" => lhs <= rhs,
+        "-gt" => lhs > rhs,
+        _ => lhs >= rhs,
+    }
+}
+
+/// This function reads an operand of an integer comparison, which may have blanks around it.
+fn integer(text: &str) -> Result<i64,String> {
+    text.trim().parse::<i64>().map_err(|_| format!("{}: integer expression expected", text))
+}
+
+/// This function gives the time that a file was last modified in nanoseconds, or None if it does not exist.
+fn modified(path: &str) -> Option<i128> {
+    let metadata = fs::metadata(path).ok()?;
+    Some(metadata.mtime() as i128 * 1_000_000_000 + metadata.mtime_nsec() as i128)
+}
+
+#[cfg(test)]
+mod test {
+    use super::evaluate;
+
+    #[test]
+    fn test_evaluate() {
+        assert_eq!(evaluate(&[]), Ok(false));
+        assert_eq!(evaluate(&[""]), Ok(false));
+        assert_eq!(evaluate(&["-n"]), Ok(true));
+        assert_eq!(evaluate(&["-z", ""]), Ok(true));
+        assert_eq!(evaluate(&["!", "-z", ""]), Ok(false));
+        assert_eq!(evaluate(&["!", "=", "x"]), Ok(false));
+        assert_eq!(evaluate(&["(", "a", ")"]), Ok(true));
+        assert_eq!(evaluate(&["abc", "<", "abd"]), Ok(true));
+        assert_eq!(evaluate(&[" 10 ", "-gt", "9"]), Ok(true));
+        assert_eq!(evaluate(&["-d", "/", "-a", "-f", "/"]), Ok(false));
+        assert_eq!(evaluate(&["-d", "/", "-o", "!", "-e", "/"]), Ok(true));
+        assert_eq!(evaluate(&["1", "-eq", "2", "-o", "(", "a", "=", "a", "-a", "b", "!=", "c", ")"]), Ok(true));
+        assert_eq!(evaluate(&["x", "-lt", "1"]), Err("x: integer expression expected".to_string()));
+        assert_eq!(evaluate(&["-R", "x"]), Ok(false));
+        assert_eq!(evaluate(&["-o", "x", "-o", "-n", "x"]), Ok(true));
+        assert_eq!(evaluate(&["-q", "x"]), Err("-q: unary operator expected".to_string()));
+        assert_eq!(evaluate(&["(", "a", "-a", "b"]), Err("`)' expected".to_string()));
+    }
+}
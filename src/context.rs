@@ -18,15 +18,51 @@ const SUP_USER_PROMPT: &str = "# ";
 #[derive(Debug, Clone)]
 pub struct Var {
     pub name: String,
-    pub value: String,
+    pub value: Value,
     pub readonly: bool,
 }
 
+/// The value of a variable.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Scalar(String),
+    /// An indexed array, whose indices do not have to follow each other.
+    Indexed(BTreeMap<usize, String>),
+    /// An associative array, whose elements are looked up by strings.
+    Associative(BTreeMap<String, String>),
+}
+
+/// The subscript of an element of an array,
+/// which is a number for an indexed array and a string for an associative array.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Key {
+    Index(usize),
+    Name(String),
+}
+
+impl Display for Key {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Key::Index(index) => write!(f, "{}", index),
+            Key::Name(name) => write!(f, "{}", name),
+        }
+    }
+}
+
 impl Var {
     pub fn new(name: &str, value: &str) -> Self {
         Self {
             name: name.to_string(),
-            value: value.to_string(),
+            value: Value::Scalar(value.to_string()),
+            readonly: false,
+        }
+    }
+
+    /// Creates an indexed array with the values as its elements, starting at index 0.
+    pub fn new_array(name: &str, values: Vec<String>) -> Self {
+        Self {
+            name: name.to_string(),
+            value: Value::Indexed(values.into_iter().enumerate().collect()),
             readonly: false,
         }
     }
@@ -35,9 +71,118 @@ impl Var {
         self.readonly = true;
     }
 
-    /// Adds an variable to the environment
+    pub fn is_array(&self) -> bool {
+        !matches!(self.value, Value::Scalar(_))
+    }
+
+    pub fn is_associative(&self) -> bool {
+        matches!(self.value, Value::Associative(_))
+    }
+
+    /// Returns the value of a scalar, or element 0 of an array which is what the name of an array stands for.
+    pub fn scalar(&self) -> &str {
+        self.element(&Key::Index(0)).unwrap_or("")
+    }
+
+    /// Returns the values of the elements in the order of their keys.
+    /// A scalar is treated as an array with a single element.
+    pub fn elements(&self) -> Vec<String> {
+        match &self.value {
+            Value::Scalar(value) => vec![value.clone()],
+            Value::Indexed(elements) => elements.values().cloned().collect(),
+            Value::Associative(elements) => elements.values().cloned().collect(),
+        }
+    }
+
+    /// Returns the indices or the keys of the elements in order.
+    pub fn keys(&self) -> Vec<String> {
+        match &self.value {
+            Value::Scalar(_) => vec!["0".to_string()],
+            Value::Indexed(elements) => elements.keys().map(|index| index.to_string()).collect(),
+            Value::Associative(elements) => elements.keys().cloned().collect(),
+        }
+    }
+
+    pub fn element(&self, key: &Key) -> Option<&str> {
+        match (&self.value, key) {
+            (Value::Scalar(value), Key::Index(0)) => Some(value),
+            (Value::Indexed(elements), Key::Index(index)) => elements.get(index).map(String::as_str),
+            (Value::Associative(elements), key) => elements.get(&key.to_string()).map(String::as_str),
+            _ => None,
+        }
+    }
+
+    /// Returns the index after the last element of an indexed array, which is where appended elements go.
+    pub fn next_index(&self) -> usize {
+        match &self.value {
+            Value::Scalar(_) => 1,
+            Value::Indexed(elements) => elements.keys().next_back().map_or(0, |index| index + 1),
+            Value::Associative(_) => 0,
+        }
+    }
+
+    /// Turns a scalar into an array whose element 0 is its old value,
+    /// or an indexed array into an associative array if `associative` is set.
+    pub fn make_array(&mut self, associative: bool) {
+        let value = std::mem::replace(&mut self.value, Value::Indexed(BTreeMap::new()));
+        self.value = match (value, associative) {
+            (Value::Scalar(value), false) => Value::Indexed(BTreeMap::from([(0, value)])),
+            (Value::Scalar(value), true) => Value::Associative(BTreeMap::from([("0".to_string(), value)])),
+            (Value::Indexed(elements), true) => {
+                Value::Associative(elements.into_iter().map(|(index, value)| (index.to_string(), value)).collect())
+            },
+            (value, _) => value,
+        };
+    }
+
+    /// Sets an element of the array, or appends to it if `append` is set.
+    /// A scalar becomes an array first.
+    pub fn set_element(&mut self, key: &Key, value: &str, append: bool) {
+        self.make_array(false);
+        let element = match (&mut self.value, key) {
+            (Value::Indexed(elements), Key::Index(index)) => elements.entry(*index).or_default(),
+            (Value::Associative(elements), key) => elements.entry(key.to_string()).or_default(),
+            _ => return,
+        };
+        if !append {
+            element.clear();
+        }
+        element.push_str(value);
+    }
+
+    /// Adds the values to the end of an indexed array.
+    /// The values for an associative array are its keys and their values in turn.
+    pub fn append_elements(&mut self, values: Vec<String>) {
+        self.make_array(false);
+        let next = self.next_index();
+        match &mut self.value {
+            Value::Indexed(elements) => elements.extend((next..).zip(values)),
+            Value::Associative(elements) => {
+                let mut values = values.into_iter();
+                while let Some(key) = values.next() {
+                    elements.insert(key, values.next().unwrap_or_default());
+                }
+            },
+            Value::Scalar(_) => {},
+        }
+    }
+
+    pub fn remove_element(&mut self, key: &Key) {
+        match (&mut self.value, key) {
+            (Value::Indexed(elements), Key::Index(index)) => {
+                elements.remove(index);
+            },
+            (Value::Associative(elements), key) => {
+                elements.remove(&key.to_string());
+            },
+            _ => {},
+        }
+    }
+
+    /// Adds an variable to the environment.
+    /// Only element 0 of an array is exported, as the environment can only hold strings.
     fn export(&mut self) {
-        env::set_var(&self.name, &self.value);
+        env::set_var(&self.name, self.scalar());
     }
 }
 /*impl ToString for Var {
@@ -47,7 +192,15 @@ impl Var {
 }*/
 impl Display for Var {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}", self.to_string())
+        match &self.value {
+            Value::Scalar(value) => write!(f, "{}={}", self.name, value),
+            _ => {
+                let elements: Vec<String> = self.keys().iter().zip(self.elements())
+                    .map(|(key, value)| format!("[{}]=\"{}\"", key, value))
+                    .collect();
+                write!(f, "{}=({})", self.name, elements.join(" "))
+            },
+        }
     }
 }
 
@@ -76,6 +229,10 @@ impl ContextManager {
     pub fn push_context_new(&mut self) {
         self.context_stack.push(Rc::new(RefCell::new(Context::default())));
     }
+    /// Pushes a new context onto the stack for a function that is called, which holds its local variables.
+    pub fn push_function_context(&mut self) {
+        self.context_stack.push(Rc::new(RefCell::new(Context { function: true, ..Context::default() })));
+    }
 
     /// Removes the last context from the stack and returns it
     /// # Panics
@@ -91,6 +248,15 @@ impl ContextManager {
     pub fn get_context(&self) -> Rc<RefCell<Context>> {
         self.context_stack.last().unwrap().clone()
     }
+    /// Returns a reference to the global Context, which is the innermost one that does not belong to a function.
+    /// New variables go there unless they are declared local.
+    pub fn get_global_context(&self) -> Rc<RefCell<Context>> {
+        self.context_stack.iter().rev().find(|context| !context.borrow().function).unwrap().clone()
+    }
+    /// Checks if the current Context belongs to a function.
+    pub fn in_function(&self) -> bool {
+        self.get_context().borrow().function
+    }
     pub fn remove_context(&mut self, name: &str) {
         self.exported_contexts.remove(name);
     }
@@ -113,6 +279,7 @@ impl ContextManager {
     /// Gets a variable from the contexts.
     /// It will never attempt to get a variable from an exported context without a namespace.
     /// It will search the Context stack in reverse order for the variable.
+    /// Positional parameters stop at the innermost function Context, so a function only sees its own arguments.
     pub fn get_var(&self, name: &str) -> Option<Rc<RefCell<Var>>> {
         if name.contains("::") {
             let mut split = name.split("::");
@@ -125,10 +292,15 @@ impl ContextManager {
             }
         }
         else {
+            let positional = name != "0" && name.chars().all(|c| c.is_ascii_digit());
             for context in self.context_stack.iter().rev() {
-                if let Some(var) = context.borrow().get_var(name) {
+                let context = context.borrow();
+                if let Some(var) = context.get_var(name) {
                     return Some(var);
                 }
+                if positional && context.function {
+                    return None;
+                }
             }
             None
         }
@@ -235,21 +407,98 @@ impl ContextManager {
         self.get_context().borrow_mut().add_function(name, func);
     }
 
-    /// Adds a variable to the current context.
+    /// Adds a variable to the context that has it, or to the global context if none does.
+    /// Assigning to the name of an array sets its element 0.
     pub fn add_var(&mut self, set: &str) {
         let mut split = set.split("=");
         let name = split.next().unwrap();
 
         for context in self.context_stack.iter_mut().rev() {
-            if context.borrow().get_var(name).is_some() {
-                if context.borrow().get_var(name).unwrap().borrow().readonly {
+            let var = context.borrow().get_var(name);
+            if let Some(var) = var {
+                if var.borrow().readonly {
                     return;
                 }
-                context.borrow_mut().add_var(set);
+                if var.borrow().is_array() {
+                    var.borrow_mut().set_element(&Key::Index(0), set.split_once('=').map_or("", |(_, value)| value), false);
+                }
+                else {
+                    context.borrow_mut().add_var(set);
+                }
                 return;
             }
         }
-        self.get_context().borrow_mut().add_var(set);
+        self.get_global_context().borrow_mut().add_var(set);
+    }
+
+    /// Adds a variable to the current context unless one of the same name is already there.
+    /// It shadows the variables of the same name further out for as long as the context is on the stack,
+    /// which is how a function binds its local variables.
+    pub fn add_var_local(&mut self, var: Var) {
+        let context = self.get_context();
+        if context.borrow().get_var(&var.name).is_none() {
+            context.borrow_mut().add_var(var);
+        }
+    }
+
+    /// Gets a variable to change its value in place, creating it as an empty array if it does not exist.
+    /// A new variable goes in the global context, or in the context of its namespace if it has one.
+    /// None is returned for a readonly variable or a namespace that does not exist.
+    fn array_var(&mut self, name: &str) -> Option<Rc<RefCell<Var>>> {
+        let var = match self.get_var(name) {
+            Some(var) => var,
+            None => {
+                let context = match name.split_once("::") {
+                    Some((namespace, _)) => self.get_context_by_name(namespace)?,
+                    None => self.get_global_context(),
+                };
+                let var_name = name.split("::").last().unwrap();
+                let var = Rc::new(RefCell::new(Var::new_array(var_name, Vec::new())));
+                context.borrow_mut().vars.insert(var_name.to_string(), var.clone());
+                var
+            },
+        };
+        if var.borrow().readonly {
+            return None;
+        }
+        Some(var)
+    }
+
+    /// Sets a variable to an array of the values, or appends them to it if `append` is set.
+    /// An associative array stays one, while any other variable becomes an indexed array.
+    pub fn set_array(&mut self, name: &str, values: Vec<String>, append: bool) {
+        if let Some(var) = self.array_var(name) {
+            let mut var = var.borrow_mut();
+            if !append {
+                var.value = match var.value {
+                    Value::Associative(_) => Value::Associative(BTreeMap::new()),
+                    _ => Value::Indexed(BTreeMap::new()),
+                };
+            }
+            var.append_elements(values);
+        }
+    }
+
+    /// Sets an element of an array, or appends to the element if `append` is set.
+    pub fn set_array_element(&mut self, name: &str, key: &Key, value: &str, append: bool) {
+        if let Some(var) = self.array_var(name) {
+            var.borrow_mut().set_element(key, value, append);
+        }
+    }
+
+    /// Removes an element of an array, leaving the others at their indices.
+    pub fn remove_array_element(&mut self, name: &str, key: &Key) {
+        if let Some(var) = self.get_var(name).filter(|var| !var.borrow().readonly) {
+            var.borrow_mut().remove_element(key);
+        }
+    }
+
+    /// Makes a variable an indexed array, or an associative array if `associative` is set,
+    /// creating it if it does not exist.
+    pub fn declare_array(&mut self, name: &str, associative: bool) {
+        if let Some(var) = self.array_var(name) {
+            var.borrow_mut().make_array(associative);
+        }
     }
     pub fn add_var_readonly(&mut self, set: &str) {
         if set.contains("::") {
@@ -300,7 +549,7 @@ impl ContextManager {
 
     /// Looks up a command in the PATH variable.
     pub fn lookup_command(&self, cmd: &str) -> Option<String> {
-        for path in self.context_stack.first().unwrap().borrow().get_var("PATH").unwrap().borrow().value.split(":") {
+        for path in self.context_stack.first().unwrap().borrow().get_var("PATH").unwrap().borrow().scalar().split(":") {
             let path = format!("{}/{}", path, cmd);
             let metadata = std::fs::metadata(&path);
             if metadata.is_ok() {
@@ -433,6 +682,8 @@ pub struct Context {
     /// Stores the functions of the context.
     functions: HashMap<String, Rc<RefCell<FunctionBody>>>,
     pub readonly_functions: HashSet<String>,
+    /// Set for the context of a running function, whose variables are local to it.
+    pub function: bool,
 }
 
 impl Context {
@@ -441,6 +692,7 @@ impl Context {
             vars,
             functions: HashMap::new(),
             readonly_functions: HashSet::new(),
+            function: false,
         }
     }
 
@@ -507,6 +759,7 @@ impl Default for Context {
             vars: HashMap::new(),
             functions: HashMap::new(),
             readonly_functions: HashSet::new(),
+            function: false,
         }
     }
 }
@@ -518,7 +771,7 @@ impl ContextUtils<&str> for Context {
     /// * `var` - The &str set that is to be added to the context.
     fn add_var(&mut self, var: &str) {
         let (name, value) = if var.contains("=") {
-            let mut split = var.split("=");
+            let mut split = var.splitn(2, '=');
             (split.next().unwrap(), split.next().unwrap())
         } else {
             (var, "")
@@ -541,7 +794,7 @@ impl ContextUtils<&str> for Context {
 
     fn add_var_readonly(&mut self, var: &str) {
         let (name, value) = if var.contains("=") {
-            let mut split = var.split("=");
+            let mut split = var.splitn(2, '=');
             (split.next().unwrap(), split.next().unwrap())
         } else {
             (var, "")
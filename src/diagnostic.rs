@@ -0,0 +1,172 @@
+use crate::lexer::{self, Token};
+use lalrpop_util::ParseError;
+
+/// The terminals of the grammar that can start a command.
+/// When the parser expects any of them the diagnostic says that it expects a command instead of listing them all.
+const COMMAND_START: [&str; 22] = [
+    "Word", "Assignment", "Number", "Subshell", "Arithmetic", "Conditional", "bang", "(", "{", "if", "for", "while", "until", "case",
+    "<", ">", ">>", "<<", "<<-", ">&", "<&", "<>",
+];
+
+/// The terminals of the grammar that make up a redirection, named together when a long list of expected tokens is summarized.
+const REDIRECTIONS: [&str; 9] = ["Number", "<", ">", ">>", "<<", "<<-", ">&", "<&", "<>"];
+
+/// The control operators of the grammar, named together when a long list of expected tokens is summarized.
+const CONTROL_OPERATORS: [&str; 7] = ["&", "&&", "||", ";", ";;", "pipe", ")"];
+
+/// The reserved words that continue or end a compound command, named together when a long list of expected tokens is summarized.
+const RESERVED_WORDS: [&str; 9] = ["then", "do", "done", "elif", "else", "fi", "esac", "in", "}"];
+
+/// The most expected tokens that a diagnostic lists.
+const MAX_EXPECTED: usize = 6;
+
+/// This function formats a parse error as a diagnostic for the user.
+/// The name is the file the input came from, or what stands in for it such as `-c`, and first_line is the
+/// line of that file that the input starts on.
+/// The diagnostic gives the line and column of the error, names the unexpected token and the tokens
+/// that were expected in words, then quotes the line with a caret under the offending token.
+pub fn parse_error(name: &str, first_line: usize, input: &str, err: &ParseError<usize, Token, lexer::Error>) -> String {
+    let (start, end, message) = match err {
+        ParseError::InvalidToken { location } => (*location, *location, "invalid token".to_string()),
+        ParseError::UnrecognizedEOF { location, expected } => {
+            (*location, *location, format!("unexpected end of input{}", expected_tokens(expected)))
+        },
+        ParseError::UnrecognizedToken { token: (start, token, end), expected } => {
+            (*start, *end, format!("unexpected {}{}", describe_token(input, *start, token, *end), expected_tokens(expected)))
+        },
+        ParseError::ExtraToken { token: (start, token, end) } => {
+            (*start, *end, format!("unexpected {}", describe_token(input, *start, token, *end)))
+        },
+        ParseError::User { error } => match error {
+            lexer::Error::UnrecognizedChar(start, _, end) => (*start, *end, error.to_string()),
+            lexer::Error::Unterminated(start, _) => (*start, *start + 1, error.to_string()),
+            lexer::Error::Conditional(start, end, _) => (*start, *end, error.to_string()),
+        },
+    };
+
+    let start = start.min(input.len());
+    let line_start = input[..start].rfind('\n').map_or(0, |pos| pos + 1);
+    let line_end = input[start..].find('\n').map_or(input.len(), |pos| start + pos);
+    let line = &input[line_start..line_end];
+    let line_number = first_line + input[..line_start].matches('\n').count();
+    let column = input[line_start..start].chars().count() + 1;
+
+    // the caret lines up under the token, keeping the tabs of the quoted line so that it lines up after them too
+    let indent: String = input[line_start..start].chars().map(|chr| if chr == '\t' { '\t' } else { ' ' }).collect();
+    let width = input[start..end.clamp(start, line_end)].chars().count().max(1);
+
+    format!("rsh: {}:{}:{}: syntax error: {}\n{}\n{}{}", name, line_number, column, message, line, indent, "^".repeat(width))
+}
+
+/// This function names a token that the parser did not expect, quoting it from the input where it has text of its own.
+fn describe_token(input: &str, start: usize, token: &Token, end: usize) -> String {
+    match token {
+        Token::EOF => "end of input".to_string(),
+        Token::Newline | Token::NewlineList => "newline".to_string(),
+        Token::HereDoc(_) => "here-document".to_string(),
+        _ => match input.get(start..end) {
+            Some(text) if !text.is_empty() => format!("`{}'", text.trim_end()),
+            _ => format!("`{}'", token),
+        },
+    }
+}
+
+/// This function turns the terminals that the parser expected into a phrase such as ", expected `then' or `;'".
+/// lalrpop gives each terminal as it is written in the grammar, quotes and all.
+fn expected_tokens(expected: &[String]) -> String {
+    let terminals: Vec<&str> = expected.iter().map(|terminal| terminal.trim_matches('"')).collect();
+
+    // a long list is more noise than help, as the parser tends to list everything that may follow a command,
+    // so the terminals are named by their kind instead
+    let mut names = name_terminals(&terminals, false);
+    if names.len() > MAX_EXPECTED {
+        names = name_terminals(&terminals, true);
+    }
+    if names.len() > MAX_EXPECTED {
+        names.truncate(MAX_EXPECTED - 1);
+        names.push("another token".to_string());
+    }
+
+    match names.split_last() {
+        None => String::new(),
+        Some((last, [])) => format!(", expected {}", last),
+        Some((last, rest)) => format!(", expected {} or {}", rest.join(", "), last),
+    }
+}
+
+/// This function names each of the terminals in words, leaving out duplicates.
+/// The terminals that can start a command are named "a command" together, and if summarize is set
+/// the redirections, control operators and reserved words are named by their kind as well.
+fn name_terminals(terminals: &[&str], summarize: bool) -> Vec<String> {
+    let command = terminals.contains(&"Word") && terminals.contains(&"if");
+
+    let mut names: Vec<String> = Vec::new();
+    if command {
+        names.push("a command".to_string());
+    }
+    for &terminal in terminals {
+        if command && COMMAND_START.contains(&terminal) {
+            continue;
+        }
+        let name = match terminal {
+            _ if summarize && REDIRECTIONS.contains(&terminal) => "a redirection".to_string(),
+            _ if summarize && CONTROL_OPERATORS.contains(&terminal) => "a control operator".to_string(),
+            _ if summarize && RESERVED_WORDS.contains(&terminal) => "a reserved word".to_string(),
+            "Word" => "a word".to_string(),
+            "Assignment" => "an assignment".to_string(),
+            "Number" => "a file descriptor".to_string(),
+            "Subshell" => "a subshell".to_string(),
+            "Arithmetic" => "an arithmetic command".to_string(),
+            "Conditional" => "a conditional command".to_string(),
+            "HereDoc" => "a here-document".to_string(),
+            "newline" | "newline_list" => "a newline".to_string(),
+            "EOF" => "end of input".to_string(),
+            "pipe" => "`|'".to_string(),
+            "bang" => "`!'".to_string(),
+            "backtick" => "``'".to_string(),
+            "space" | "tab" | "comment" => continue,
+            other => format!("`{}'", other),
+        };
+        if !names.contains(&name) {
+            names.push(name);
+        }
+    }
+    names
+}
+
+#[cfg(test)]
+mod test {
+    use super::parse_error;
+    use crate::ast::grammar;
+    use crate::lexer::Lexer;
+
+    fn diagnose(input: &str) -> String {
+        let err = grammar::CompleteCommandParser::new().parse(input, Lexer::new(input)).unwrap_err();
+        parse_error("test.sh", 1, input, &err)
+    }
+
+    #[test]
+    fn test_parse_error() {
+        let message = diagnose("echo a\nif true; fi\n");
+        println!("{}", message);
+        assert!(message.starts_with("rsh: test.sh:2:10: syntax error: unexpected `fi'"));
+        assert!(message.contains("`then'"));
+        assert!(message.ends_with("\nif true; fi\n         ^^"));
+
+        let message = diagnose("echo 'a\nb");
+        println!("{}", message);
+        assert!(message.starts_with("rsh: test.sh:1:6: syntax error: unexpected end of input while looking for matching `''"));
+
+        let message = diagnose("true &&");
+        println!("{}", message);
+        assert!(message.contains("unexpected end of input, expected a command"));
+
+        let message = diagnose("fi fi");
+        println!("{}", message);
+        assert!(message.contains("unexpected `fi', expected a command"));
+
+        let message = diagnose("case a in x) ;; esac esac");
+        println!("{}", message);
+        assert!(message.contains("unexpected `esac', expected a control operator, a redirection, end of input, a reserved word or a newline"));
+    }
+}
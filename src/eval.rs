@@ -1,22 +1,33 @@
 use crate::ast::*;
 use crate::jobs::Process;
+use crate::lexer::Lexer;
 use std::ffi::CString;
 use crate::jobs;
 use crate::builtins;
 use crate::shell;
 use crate::trap;
+use crate::expand;
+use crate::process::CommandExitStatus;
+use crate::context::Var;
 use nix::errno::Errno;
-use nix::sys::wait::WaitStatus;
-use std::fs::OpenOptions;
-use std::os::unix::io::AsRawFd;
+use nix::sys::wait::{waitpid, WaitStatus};
+use nix::fcntl::{fcntl, FcntlArg};
+use std::io::{Read, Write};
+use std::fs::{File, OpenOptions};
+use std::os::unix::io::{AsRawFd, FromRawFd};
 use std::mem;
 use std::env;
-use std::sync::atomic::{AtomicI32, Ordering};
-use crate::log;
+use std::sync::atomic::{AtomicI32, AtomicUsize, Ordering};
 
 use std::os::unix::io::RawFd;
 use nix::unistd::{close, dup2, pipe,execv, fork, getpid, setpgid, ForkResult, Pid};
 
+/// Counts the here-documents so that each one gets a temporary file of its own
+static HERE_DOCUMENT_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+/// Stores the exit status of the last command substitution of the command that is being expanded, or -1 if it has none
+static SUBSTITUTION_STATUS: AtomicI32 = AtomicI32::new(-1);
+
 /// Stores the exit status of the last command
 pub static mut EXIT_STATUS: AtomicI32 = AtomicI32::new(0);
 
@@ -44,6 +55,9 @@ pub fn get_exit_code() -> i32 {
 /// the function parse_tree
 pub fn eval(ast: &mut CompleteCommand) -> Result<i32,String> {
 
+    // a break or continue can't unwind past the command line that it was on
+    shell::clear_loop_skip();
+
     let list = match &mut ast.list {
         Some(list) => list,
         None => return Ok(0),
@@ -63,6 +77,9 @@ fn parse_tree(list: &mut Vec<AndOr>) -> Result<i32,String> {
     for and_or in list.iter_mut() {
 
         status = eval_and_or(and_or)?;
+        if shell::get_loop_skip().is_some() {
+            break;
+        }
     } 
 
     Ok(status)
@@ -80,6 +97,9 @@ fn eval_and_or(and_or: &mut AndOr) -> Result<i32,String> {
     }
     else {
         let status = eval_and_or(and_or.and_or.as_mut().unwrap())?;
+        if shell::get_loop_skip().is_some() {
+            return Ok(status);
+        }
         match and_or.conditional_exec {
             Some(ConditionalExec::And) => {
                 if status != 0 {
@@ -102,27 +122,52 @@ fn eval_and_or(and_or: &mut AndOr) -> Result<i32,String> {
 }
 
 /// This is where we evaluate a pipeline
+/// A pipeline that starts with `!` has its exit status inverted, unless it runs in the background.
+fn eval_pipeline(pipeline: &mut Pipeline) -> Result<i32,String> {
+    let status = run_pipeline(pipeline)?;
+    if pipeline.bang && !pipeline.background {
+        let status = if status == 0 { 1 } else { 0 };
+        set_exit_status(status);
+        return Ok(status);
+    }
+    Ok(status)
+}
+
+/// This function runs the commands of a pipeline.
 /// We iterate through the pipeline and evaluate each command in the pipeline
 /// If the evaluation creates Processes we then create a Job and execute the job.
 /// If the job is not a background job we wait for the job to finish and return the status
-/// Functions are handled here in a special way. If the function is not in the background, we
+/// Functions are handled here in a special way. If the function runs on its own and not in the background, we
 /// execute the function before going into the the fork and exec loop, removing it from the pipeline.
 /// We also have to remove the job from the shell to prevent a panic when trying to wait for the job.
-/// If the function is in the background we just add it to the pipeline and let the fork and exec loop
-/// handle it.
+/// If the function is in the background or part of a longer pipeline we just add it to the pipeline
+/// and let the fork and exec loop handle it, the same as a builtin.
 /// Interupts are blocked during the fork and exec part to prevent being interupted by a signal.
-fn eval_pipeline(pipeline: &mut Pipeline) -> Result<i32,String> {
+fn run_pipeline(pipeline: &mut Pipeline) -> Result<i32,String> {
 
     let background = pipeline.background;
     let mut pipeline: &mut PipeSequence = &mut pipeline.pipe_sequence;
+    // builtins and compound commands only need a process of their own when they run alongside other commands
+    let fork = background || pipeline.0.len() > 1;
     
     let mut processes = Vec::new();
     let mut commands = Vec::new();
 
+    // the process substitutions of this pipeline are kept apart from those of a pipeline that it is nested in
+    let outer = shell::take_process_substitutions();
+
     //block interrupts
     trap::interrupts_off();
     for command in pipeline.iter_mut() {
-        let process = eval_command(command)?;
+        let process = match eval_command(command, fork) {
+            Ok(process) => process,
+            Err(err) => {
+                wait_process_substitutions(shell::take_process_substitutions());
+                shell::set_process_substitutions(outer);
+                trap::interrupts_on();
+                return Err(err);
+            },
+        };
         if process.is_none() {
             break;   
         }
@@ -133,31 +178,41 @@ fn eval_pipeline(pipeline: &mut Pipeline) -> Result<i32,String> {
         }
     }
     
+    let mut process_substitutions = shell::take_process_substitutions();
+    shell::set_process_substitutions(outer);
+
     if processes.len() == 0 {
-        return Ok(0);
+        wait_process_substitutions(process_substitutions);
+        trap::interrupts_on();
+        set_pipe_status(&[get_exit_code()]);
+        return Ok(get_exit_code());
     }
    
     let mut remove_index: Vec<usize> = Vec::new();
     
-    if !background {
+    if !fork {
         for (index,command) in commands.iter_mut().enumerate() {
+            let command = match command {
+                Command::SimpleCommand(command) => command,
+                _ => continue,
+            };
             if shell::is_function(&command.name) {
                 remove_index.push(index);
             
                 let result = eval_function(command);
                 
-                match result {
-                    Ok(_) => {},
+                let status = match result {
+                    Ok(status) => status,
                     Err(_) => {
                         eprintln!("{}: Command not found\n", command.name);
                         std::process::exit(1);
                     }
-                }
+                };
                 if shell::get_forked() {
-                    std::process::exit(0);
+                    std::process::exit(status);
                 }
                 else {
-                    set_exit_status(0);
+                    set_exit_status(status);
                 }
             }
 
@@ -220,21 +275,59 @@ fn eval_pipeline(pipeline: &mut Pipeline) -> Result<i32,String> {
                     close(pip.1).unwrap();
                 }
 
-                eval_prefix_suffix(commands[count].prefix_suffix());
+                let command = match &mut commands[count] {
+                    Command::SimpleCommand(command) => command,
+                    Command::CompoundCommand(compound_command, redirect_list) => {
+                        if let Some(redirect_list) = redirect_list {
+                            if let Err(err) = redirect_list.expand().and_then(|redirects| eval_redirect(&redirects)) {
+                                eprintln!("rsh: {}", err);
+                                std::process::exit(1);
+                            }
+                        }
+                        // this process is the subshell so its list is run directly instead of forking again
+                        let result = match compound_command {
+                            CompoundCommand::SubShell(subshell) => eval_compound_list(&mut subshell.compound_list),
+                            _ => eval_compound_command(compound_command),
+                        };
+                        match result {
+                            Ok(status) => std::process::exit(status),
+                            Err(err) => {
+                                eprintln!("{}", err);
+                                std::process::exit(1);
+                            }
+                        }
+                    },
+                    Command::FunctionDefinition(_) => unreachable!(),
+                };
+
+                if let Err(err) = eval_prefix_suffix(command.prefix_suffix()) {
+                    eprintln!("rsh: {}", err);
+                    std::process::exit(1);
+                }
                 
                 //println!("executing: {:?}", process.argv);
                 
-                if shell::is_function(&commands[count].name) {
-                    let result = eval_function(&mut commands[count]);
+                if shell::is_function(&command.name) {
+                    let result = eval_function(command);
                     
                     match result {
-                        Ok(_) => {},
+                        Ok(status) => std::process::exit(status),
                         Err(_) => {
-                            eprintln!("{}: Command not found\n", commands[count].name);
+                            eprintln!("{}: Command not found\n", command.name);
+                            std::process::exit(1);
+                        }
+                    }
+                }
+                else if check_if_builtin(&command.name) {
+                    let result = eval_builtin(command);
+                    let _ = std::io::stdout().flush();
+                    match result {
+                        Ok(_) => std::process::exit(get_exit_code()),
+                        Err(err) => {
+                            eprintln!("rsh: {}", err);
                             std::process::exit(1);
                         }
                     }
-                    std::process::exit(0);
                 }
                 else {
                     match temp_exec(process) {
@@ -261,7 +354,16 @@ fn eval_pipeline(pipeline: &mut Pipeline) -> Result<i32,String> {
 
             count += 1;
         }
-        
+
+        // process substitutions are waited for as part of the job, and now that every process
+        // has a copy of the shell's ends of their pipes those can be closed
+        if proc_count > 0 {
+            for (process, fd) in mem::take(&mut process_substitutions) {
+                let _ = close(fd);
+                shell::update_pid_table(job_id, process.pid);
+                procs.push(process);
+            }
+        }
     }
         if !background && proc_count > 0 && job.borrow().processes.len() > 0{
             //eprintln!("waiting for job");
@@ -273,11 +375,13 @@ fn eval_pipeline(pipeline: &mut Pipeline) -> Result<i32,String> {
             }
             shell::delete_job(id);
 
-            match status {
-                WaitStatus::Exited(_, status) => {
-                    set_exit_status(status);
-                },
-                _ => (),
+            if matches!(status, WaitStatus::Exited(_, _) | WaitStatus::Signaled(_, _, _)) {
+                let statuses: Vec<i32> = job.borrow().processes.iter()
+                    .take(proc_count)
+                    .filter_map(|process| process.status.and_then(exit_code))
+                    .collect();
+                set_pipe_status(&statuses);
+                set_exit_status(pipeline_status(&statuses));
             }
         }
         else if background && proc_count > 0  && job.borrow().processes.len() > 0 {
@@ -289,6 +393,8 @@ fn eval_pipeline(pipeline: &mut Pipeline) -> Result<i32,String> {
                 job.job_id
             };
             shell::delete_job(id);
+            wait_process_substitutions(process_substitutions);
+            set_pipe_status(&[get_exit_code()]);
         }
 
     //let job = temp_exec(processes)?;
@@ -300,62 +406,281 @@ fn eval_pipeline(pipeline: &mut Pipeline) -> Result<i32,String> {
     Ok(get_exit_code())
 }
 
+/// This function runs the command of a `$(...)` or backquote command substitution in a child and returns
+/// what it writes to standard output without the newlines at the end. Standard error is left alone.
+/// The output is read while the child runs, so a command with more output than a pipe holds does not block,
+/// and the exit status of the command becomes `$?`.
+pub fn command_substitution(command: &str) -> Result<String,String> {
+    let lexer = Lexer::new(command);
+    let mut ast = grammar::CompleteCommandParser::new()
+        .parse(command, lexer)
+        .map_err(|_| format!("syntax error in command substitution: {}", command))?;
+
+    let (read, write) = pipe().map_err(|err| format!("command substitution: {}", err))?;
+
+    match unsafe {fork()} {
+        Ok(ForkResult::Child) => {
+            shell::set_forked(true);
+            jobs::fork_reset();
+            let _ = close(read);
+            if dup2(write, 1).is_err() {
+                std::process::exit(1);
+            }
+            let _ = close(write);
+            if let Err(err) = eval(&mut ast) {
+                eprintln!("rsh: {}", err);
+            }
+            let _ = std::io::stdout().flush();
+            std::process::exit(get_exit_code());
+        },
+        Ok(ForkResult::Parent { child }) => {
+            let _ = close(write);
+            let mut output = Vec::new();
+            // the read end is closed when the file is dropped
+            let result = unsafe { File::from_raw_fd(read) }.read_to_end(&mut output);
+            let status = waitpid(child, None).ok().and_then(exit_code).unwrap_or(1);
+            set_exit_status(status);
+            SUBSTITUTION_STATUS.store(status, Ordering::Relaxed);
+            result.map_err(|err| format!("command substitution: {}", err))?;
+
+            let output = String::from_utf8_lossy(&output);
+            Ok(output.trim_end_matches('\n').to_string())
+        },
+        Err(err) => {
+            let _ = close(read);
+            let _ = close(write);
+            Err(format!("command substitution: {}", err))
+        },
+    }
+}
+
+/// This function starts the command of a `<(...)` or `>(...)` process substitution in a child that is connected
+/// to the shell by a pipe, and returns the `/dev/fd` path of the shell's end of the pipe.
+/// With input set the command writes into the pipe and the path is read from, otherwise the other way round.
+/// The end stays open so the processes of the pipeline inherit it, and run_pipeline closes it once they are forked.
+pub fn process_substitution(input: bool, command: &str) -> Result<String,String> {
+    let lexer = Lexer::new(command);
+    let mut ast = grammar::CompleteCommandParser::new()
+        .parse(command, lexer)
+        .map_err(|_| format!("syntax error in process substitution: {}", command))?;
+
+    let (read, write) = pipe().map_err(|err| format!("process substitution: {}", err))?;
+    let (keep, give, fd) = if input { (read, write, 1) } else { (write, read, 0) };
+
+    match unsafe {fork()} {
+        Ok(ForkResult::Child) => {
+            shell::set_forked(true);
+            jobs::fork_reset();
+            let _ = close(keep);
+            if dup2(give, fd).is_err() {
+                std::process::exit(1);
+            }
+            let _ = close(give);
+            let status = eval(&mut ast).unwrap_or(1);
+            let _ = std::io::stdout().flush();
+            std::process::exit(status);
+        },
+        Ok(ForkResult::Parent { child }) => {
+            let _ = close(give);
+            let name = format!("{}({})", if input { '<' } else { '>' }, command);
+            let mut process = Process::new(Vec::new(), name.clone(), name);
+            process.set_pid(child);
+            shell::add_process_substitution(process, keep);
+            Ok(format!("/dev/fd/{}", keep))
+        },
+        Err(err) => {
+            let _ = close(keep);
+            let _ = close(give);
+            Err(format!("process substitution: {}", err))
+        },
+    }
+}
+
+/// This function closes the shell's ends of the pipes of process substitutions and waits for their commands
+/// as a job of their own, which is needed when the pipeline that they belong to has no job to add them to.
+/// This function should be called with interrupts disabled.
+fn wait_process_substitutions(process_substitutions: Vec<(Process, RawFd)>) {
+    if process_substitutions.is_empty() {
+        return;
+    }
+    let processes: Vec<Process> = process_substitutions.into_iter().map(|(process, fd)| {
+        let _ = close(fd);
+        process
+    }).collect();
+    let pids: Vec<Pid> = processes.iter().map(|process| process.pid).collect();
+
+    let job = shell::create_job(processes, false);
+    let job_id = job.borrow().job_id;
+    for pid in pids {
+        shell::update_pid_table(job_id, pid);
+    }
+    jobs::wait_for_job(Some(job));
+    shell::delete_job(job_id);
+}
+
+/// This function turns the status of a finished process into an exit code.
+/// A process that was killed by a signal gets 128 plus the number of the signal.
+fn exit_code(status: WaitStatus) -> Option<i32> {
+    match status {
+        WaitStatus::Exited(_, status) => Some(status),
+        WaitStatus::Signaled(_, signal, _) => Some(128 + signal as i32),
+        _ => None,
+    }
+}
+
+/// This function gives the exit status of a pipeline from the exit codes of its processes.
+/// It is the code of the last process, or with pipefail set, the code of the last process that failed.
+fn pipeline_status(statuses: &[i32]) -> i32 {
+    if shell::get_option("pipefail") {
+        statuses.iter().rev().find(|status| **status != 0).copied().unwrap_or(0)
+    }
+    else {
+        statuses.last().copied().unwrap_or(0)
+    }
+}
+
+/// This function stores the exit codes of the processes of the last pipeline in the PIPESTATUS array.
+fn set_pipe_status(statuses: &[i32]) {
+    shell::set_array("PIPESTATUS", statuses.iter().map(|status| status.to_string()).collect(), false);
+}
+
 /// This command evaluates a Command and returns a None if the command is a function definition or a shell builtin.
-/// if the command is not one of the above two then it returns a tuple with a Process and the SimpleCommand that
+/// if the command is not one of the above two then it returns a tuple with a Process and the Command that
 /// made the Process.
-fn eval_command(command: &mut Command) -> Result<Option<(Process,SimpleCommand)>,String> {
+/// Builtins and compound commands are run by the shell itself unless fork is set, or the compound command
+/// is a subshell, in which case they get a Process that evaluates them in a forked child.
+fn eval_command(command: &mut Command, fork: bool) -> Result<Option<(Process,Command<String>)>,String> {
 
     match command {
         Command::SimpleCommand(simple_command) => {
-            return eval_simple_command(simple_command);
+            let process = eval_simple_command(simple_command, fork)?;
+            return Ok(process.map(|(process, simple_command)| (process, Command::SimpleCommand(simple_command))));
         },
         Command::FunctionDefinition(function_definition) => {
             return eval_function_definition(function_definition);
         },
-        _ => {
-            return Err("Not implemented".to_string());
+        Command::CompoundCommand(compound_command, redirect_list) => {
+            if fork || matches!(compound_command, CompoundCommand::SubShell(_)) {
+                let name = compound_command_name(compound_command);
+                let process = Process::new(Vec::new(), name.clone(), name);
+                return Ok(Some((process, Command::CompoundCommand(compound_command.clone(), redirect_list.clone()))));
+            }
+            let status = eval_redirected_compound_command(compound_command, redirect_list)?;
+            set_exit_status(status);
+            return Ok(None);
+        },
+    }
+
+}
+
+/// This function gives a short name for a compound command that is used when it is shown as a job.
+fn compound_command_name(compound_command: &CompoundCommand) -> String {
+    match compound_command {
+        CompoundCommand::BraceGroup(_) => "{ ... }",
+        CompoundCommand::SubShell(_) => "( ... )",
+        CompoundCommand::ForClause(_) => "for ... done",
+        CompoundCommand::CaseClause(_) => "case ... esac",
+        CompoundCommand::IfClause(_) => "if ... fi",
+        CompoundCommand::WhileClause(_) => "while ... done",
+        CompoundCommand::UntilClause(_) => "until ... done",
+        CompoundCommand::ArithmeticCommand(_) => "(( ... ))",
+        CompoundCommand::ConditionalCommand(_) => "[[ ... ]]",
+    }.to_string()
+}
+
+/// This function evaluates a compound command in the shell itself with its redirections applied.
+fn eval_redirected_compound_command(compound_command: &mut CompoundCommand, redirect_list: &Option<RedirectList>) -> Result<i32,String> {
+    let redirect_list = match redirect_list {
+        Some(redirect_list) => redirect_list,
+        None => return eval_compound_command(compound_command),
+    };
+    let redirects = match redirect_list.expand() {
+        Ok(redirects) => redirects,
+        Err(err) => {
+            eprintln!("rsh: {}", err);
+            return Ok(1);
+        },
+    };
+    eval_redirected(&redirects, || eval_compound_command(compound_command))
+}
+
+/// This function runs something in the shell itself with the redirections applied.
+/// The file descriptors that the redirections change are saved before and restored afterwards.
+/// If a redirection fails its error is printed and the status is 1 without running anything.
+fn eval_redirected(redirects: &[IoRedirect<String>], eval: impl FnOnce() -> Result<i32,String>) -> Result<i32,String> {
+    if redirects.is_empty() {
+        return eval();
+    }
+
+    // a descriptor that was not open is saved as None and closed again afterwards
+    let mut saved: Vec<(RawFd, Option<RawFd>)> = Vec::new();
+    for redirect in redirects.iter() {
+        let fd = redirected_fd(redirect);
+        if saved.iter().all(|(saved_fd, _)| *saved_fd != fd) {
+            saved.push((fd, fcntl(fd, FcntlArg::F_DUPFD_CLOEXEC(10)).ok()));
         }
-        
     }
 
+    let _ = std::io::stdout().flush();
+    let result = match eval_redirect(redirects) {
+        Ok(()) => eval(),
+        Err(err) => {
+            eprintln!("rsh: {}", err);
+            Ok(1)
+        },
+    };
+
+    let _ = std::io::stdout().flush();
+    for (fd, copy) in saved {
+        match copy {
+            Some(copy) => {
+                let _ = dup2(copy, fd);
+                let _ = close(copy);
+            },
+            None => {
+                let _ = close(fd);
+            },
+        }
+    }
+    result
 }
 
 /// This function evaluates a function definition and adds it to the current context's function table.
-fn eval_function_definition(function_definition: &mut FunctionDefinition) -> Result<Option<(Process,SimpleCommand)>,String> {
+fn eval_function_definition(function_definition: &mut FunctionDefinition) -> Result<Option<(Process,Command<String>)>,String> {
     let name = &function_definition.name;
     let body = function_definition.function_body.clone();
     shell::add_function(&name, body);
     Ok(None)
 }
 
-/// This function evaluates a simple command and returns a tuple with a Process and the SimpleCommand that
-/// made the Process.
-fn eval_simple_command(simple_command: &mut SimpleCommand) -> Result<Option<(Process, SimpleCommand)>,String> {
-   
-    if check_if_builtin(&simple_command.name) {
-        return eval_builtin(simple_command);
-    }
+/// This function evaluates a simple command and returns a tuple with a Process and the expanded SimpleCommand that
+/// made the Process. Builtins are run once the command has been expanded, since only then is its name known,
+/// with its redirections applied around them.
+/// If fork_builtin is set a builtin gets a Process as well, which runs it in a forked child.
+fn eval_simple_command(simple_command: &mut SimpleCommand, fork_builtin: bool) -> Result<Option<(Process, SimpleCommand<String>)>,String> {
 
-    
-    //todo make this one function to resolve problems
-    /*log!("eval_simple_command: {:?}", simple_command);
-    simple_command.remove_whitespace();
-    simple_command.eval_double_quotes();
-    log!("eval_double_quotes: {:?}", simple_command);
     simple_command.alias_lookup();
-    log!("alias_lookup: {:?}", simple_command);
-    simple_command.expand_vars();
-    log!("expand_vars: {:?}", simple_command);
-    simple_command.remove_double_quotes();
-    log!("remove_double_quotes: {:?}", simple_command);
-    simple_command.remove_single_quotes();
-    log!("remove_single_quotes: {:?}", simple_command);*/
+    SUBSTITUTION_STATUS.store(-1, Ordering::Relaxed);
+    let simple_command = match simple_command.expand() {
+        Ok(simple_command) => simple_command,
+        Err(err) => {
+            set_exit_status(1);
+            return Err(err);
+        },
+    };
 
-    simple_command.alias_lookup();
-    simple_command.expand_vars();
-    simple_command.eval();
-   
-    
+    if check_if_builtin(&simple_command.name) && !fork_builtin {
+        let (prefix, suffix) = simple_command.prefix_suffix();
+        let redirects: Vec<IoRedirect<String>> = prefix.map(|prefix| prefix.io_redirect.clone()).unwrap_or_default().into_iter()
+            .chain(suffix.map(|suffix| suffix.io_redirect.clone()).unwrap_or_default())
+            .collect();
+        let status = eval_redirected(&redirects, || {
+            eval_builtin(&simple_command)?;
+            Ok(get_exit_code())
+        })?;
+        set_exit_status(status);
+        return Ok(None);
+    }
 
     //log!("eval_simple_command: {:?}", simple_command);
     
@@ -364,67 +689,140 @@ fn eval_simple_command(simple_command: &mut SimpleCommand) -> Result<Option<(Pro
 
     let process = Process::new(argv,simple_command.name.clone(),simple_command.cmd());
 
-    Ok(Some((process,simple_command.clone())))// this clone is bad and should be replaced
+    Ok(Some((process,simple_command)))
 }
 
 /// This function evaluates a SimpleCommand's Prefix and Suffix.
-fn eval_prefix_suffix(prefix_suffix: (Option<&Prefix>, Option<&Suffix>)) {
+fn eval_prefix_suffix(prefix_suffix: (Option<&Prefix<String>>, Option<&Suffix<String>>)) -> Result<(),String> {
     let (prefix, suffix) = prefix_suffix;
     if prefix.is_some() {
-        eval_redirect(&prefix.unwrap().io_redirect);
+        eval_redirect(&prefix.unwrap().io_redirect)?;
         eval_assignment(&prefix.unwrap().assignment);
     }
     if suffix.is_some() {
-        eval_redirect(&suffix.unwrap().io_redirect);
+        eval_redirect(&suffix.unwrap().io_redirect)?;
     }
+    Ok(())
 }
 
 /// This function evaluates an assignment in a SimpleCommand's Prefix.
-fn eval_assignment(assignment: &Vec<String>) {
-    for assign in assignment.iter() {
-        let assign = assign.split('=').collect::<Vec<&str>>();
-        env::set_var(&assign[0], &assign[1]);
+/// The environment only holds strings, so an array is exported as its first element.
+fn eval_assignment(assignment: &[Assignment<String>]) {
+    for assign in assignment.iter().filter(|assign| assign.index.is_none()) {
+        let value = match &assign.value {
+            AssignmentValue::Scalar(value) => value.as_str(),
+            AssignmentValue::Array(values) => values.first().map_or("", String::as_str),
+        };
+        env::set_var(&assign.name, value);
     }
 }
 
-/// This function evaluates an IoRedirect in a SimpleCommand's Prefix or Suffix.
-fn eval_redirect(redirect: &Vec<IoRedirect>) {
+/// This function evaluates the IoRedirects in a SimpleCommand's Prefix or Suffix.
+/// The redirections are applied from left to right and each one sees the file descriptors
+/// as the ones before it left them, so `2>&1 >out` and `>out 2>&1` do different things.
+fn eval_redirect(redirect: &[IoRedirect<String>]) -> Result<(),String> {
     for redir in redirect.iter() {
-        if redir.io_file.is_some() {
-            let io_file = redir.io_file.as_ref().unwrap();
-            match &io_file.redirect_type {
+        let fd = redirected_fd(redir);
+        if let Some(io_here) = &redir.io_here {
+            let file = here_document_file(&io_here.body()?).map_err(|err| format!("here-document: {}", err))?;
+            move_fd(file, fd)?;
+        }
+        if let Some(io_file) = &redir.io_file {
+            let filename = &io_file.filename;
+            let file = match &io_file.redirect_type {
                 RedirectType::Input => {
-                    let file = OpenOptions::new()
+                    OpenOptions::new()
                         .read(true)
-                        .open(&io_file.filename)
-                        .unwrap();
-                    
-                    dup2(file.as_raw_fd(), 0).unwrap();
-                    mem::forget(file);
+                        .open(filename)
                 },
-                RedirectType::Output => {
-                    let file = OpenOptions::new()
+                RedirectType::Output | RedirectType::Clobber => {
+                    OpenOptions::new()
                         .write(true)
                         .create(true)
-                        .open(&io_file.filename)
-                        .unwrap();
-                    dup2(file.as_raw_fd(), 1).unwrap();
-                    mem::forget(file);
+                        .truncate(true)
+                        .open(filename)
                 },
                 RedirectType::Append => {
-                    let file = OpenOptions::new()
+                    OpenOptions::new()
                         .write(true)
                         .append(true)
                         .create(true)
-                        .open(&io_file.filename)
-                        .unwrap();
-                    dup2(file.as_raw_fd(), 1).unwrap();
-                    mem::forget(file);
+                        .open(filename)
                 },
-                _ => (),
-            }
+                RedirectType::ReadWrite => {
+                    OpenOptions::new()
+                        .read(true)
+                        .write(true)
+                        .create(true)
+                        .open(filename)
+                },
+                RedirectType::DuplicateInput | RedirectType::DuplicateOutput => {
+                    duplicate_fd(filename, fd)?;
+                    continue;
+                },
+            };
+            let file = file.map_err(|err| format!("{}: {}", filename, err))?;
+            move_fd(file, fd)?;
         }
     }
+    Ok(())
+}
+
+/// This function gives the file descriptor that an IoRedirect changes.
+/// Without an explicit number it is standard input for `<`, `<&`, `<>` and here-documents
+/// and standard output for everything else.
+fn redirected_fd(redirect: &IoRedirect<String>) -> RawFd {
+    if let Some(fd) = redirect.io_number {
+        return fd;
+    }
+    match &redirect.io_file {
+        Some(IoFile { redirect_type: RedirectType::Input | RedirectType::DuplicateInput | RedirectType::ReadWrite, .. }) => 0,
+        Some(_) => 1,
+        None => 0,
+    }
+}
+
+/// This function makes fd refer to an open file and closes the file's own descriptor.
+fn move_fd(file: File, fd: RawFd) -> Result<(),String> {
+    if file.as_raw_fd() == fd {
+        // the file was opened on the descriptor we wanted because it was closed before
+        mem::forget(file);
+        return Ok(());
+    }
+    dup2(file.as_raw_fd(), fd).map_err(|err| format!("{}: {}", fd, err.desc()))?;
+    Ok(())
+}
+
+/// This function makes fd a copy of the file descriptor named by target for `n>&m` and `n<&m`
+/// or closes fd if target is `-`.
+fn duplicate_fd(target: &str, fd: RawFd) -> Result<(),String> {
+    if target == "-" {
+        let _ = close(fd);
+        return Ok(());
+    }
+    let target_fd = match target.parse::<RawFd>() {
+        Ok(target_fd) => target_fd,
+        Err(_) => return Err(format!("{}: ambiguous redirect", target)),
+    };
+    if target_fd != fd {
+        dup2(target_fd, fd).map_err(|err| format!("{}: {}", target, err.desc()))?;
+    }
+    Ok(())
+}
+
+/// This function writes the body of a here-document to a temporary file and opens it for reading.
+/// The file is removed right away so that it goes away once the command is done with it.
+fn here_document_file(body: &str) -> std::io::Result<File> {
+    let count = HERE_DOCUMENT_COUNT.fetch_add(1, Ordering::Relaxed);
+    let path = env::temp_dir().join(format!("rsh-heredoc-{}-{}", getpid(), count));
+    let mut file = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&path)?;
+    file.write_all(body.as_bytes())?;
+    let reader = File::open(&path);
+    let _ = std::fs::remove_file(&path);
+    reader
 }
 
 /// This function checks if a command is a shell builtin.
@@ -439,49 +837,41 @@ fn check_if_builtin(cmd_name: &str) -> bool {
         "export" => true,
 	    "eval" => true,
         "unset" => true,
+        "declare" | "local" => true,
         "pwd" => true,
+        "test" | "[" => true,
         "readonly" => true,
         "exec" => true,
         "." | "source" => true,
+        "break" | "continue" => true,
+        "set" | "shopt" => true,
         "" => true,
         _ => false
     }
 }
 
-/// This function evaluates a shell builtin. We should handle the error properly here.
-fn eval_builtin(command: &mut SimpleCommand) -> Result<Option<(Process,SimpleCommand)>,String> {
-
-    /*command.remove_whitespace();
-    log!("eval_simple_command: {:?}", command);
-    command.eval_double_quotes();
-    log!("eval_double_quotes: {:?}", command);
-    command.alias_lookup();
-    log!("alias_lookup: {:?}", command);
-    command.expand_vars();
-    log!("expand_vars: {:?}", command);
-    command.remove_double_quotes();
-    log!("remove_double_quotes: {:?}", command);
-    command.remove_single_quotes();
-    log!("remove_single_quotes: {:?}", command);*/
-
-
-    command.alias_lookup();
-    command.expand_vars();
-    command.eval();
-
+/// This function evaluates a shell builtin.
+/// A builtin that fails has its error printed and exits with a status of 1, or 2 if it was used wrongly,
+/// so that the commands after it still run and conditionals and `&&` and `||` can test it.
+fn eval_builtin(command: &SimpleCommand<String>) -> Result<Option<(Process,SimpleCommand<String>)>,String> {
 
-    let result = call_builtin(command);
-
-    if result.is_err() {
-        return Err(format!("{}", result.err().unwrap()));
+    // builtins that report a status of their own (like test) overwrite this, a command that only assigns
+    // has the status of its last command substitution and a return without a number keeps the last status
+    let substitution_status = SUBSTITUTION_STATUS.load(Ordering::Relaxed);
+    match command.name.as_str() {
+        "" if substitution_status >= 0 => set_exit_status(substitution_status),
+        "return" => {},
+        _ => set_exit_status(0),
     }
-    else {
-        return Ok(None)
+
+    if let Err(err) = call_builtin(command) {
+        eprintln!("rsh: {}", err);
+        set_exit_status(if err.kind() == std::io::ErrorKind::InvalidInput { 2 } else { 1 });
     }
-    
+    Ok(None)
 }
 
-fn call_builtin(command: &mut SimpleCommand) -> Result<Option<(Process,SimpleCommand)>,std::io::Error> {
+fn call_builtin(command: &SimpleCommand<String>) -> Result<Option<(Process,SimpleCommand<String>)>,std::io::Error> {
 
     match command.name.as_str() {
         "cd" => {
@@ -524,10 +914,22 @@ fn call_builtin(command: &mut SimpleCommand) -> Result<Option<(Process,SimpleCom
             builtins::unset(command)?;
             Ok(None)
         },
+        "declare" => {
+            builtins::declare(command)?;
+            Ok(None)
+        },
+        "local" => {
+            builtins::local(command)?;
+            Ok(None)
+        },
         "pwd" => {
             builtins::pwd()?;
             Ok(None)
         },
+        "test" | "[" => {
+            builtins::test(command)?;
+            Ok(None)
+        },
         "readonly" => {
             builtins::readonly(command)?;
             Ok(None)
@@ -540,6 +942,22 @@ fn call_builtin(command: &mut SimpleCommand) -> Result<Option<(Process,SimpleCom
             builtins::source(command)?;
             Ok(None)
         },
+        "break" => {
+            builtins::break_cmd(command)?;
+            Ok(None)
+        },
+        "continue" => {
+            builtins::continue_cmd(command)?;
+            Ok(None)
+        },
+        "set" => {
+            builtins::set(command)?;
+            Ok(None)
+        },
+        "shopt" => {
+            builtins::shopt(command)?;
+            Ok(None)
+        },
         "" => {
             builtins::assignment(command)?;
             Ok(None)
@@ -556,15 +974,15 @@ fn check_if_function(cmd_name: &str) -> bool {
 }
 
 /// This function evaluates a shell function.
-fn eval_function(command: &mut SimpleCommand) -> Result<i32,&'static str> {
+fn eval_function(command: &SimpleCommand<String>) -> Result<i32,&'static str> {
     let function = shell::get_function(&command.name);
     if function.is_none() {
         return Err("Function not found");
     }
     //eprintln!("{:?}", function.clone().unwrap());
     //eprintln!("{:?}", command);
-    shell::push_context_new();
-    shell::add_var_context(&format!("0={}", command.name));
+    shell::push_function_context();
+    shell::add_var_local(Var::new("0", &command.name));
     //eprintln!("0={}", command.name);
     if command.suffix.is_some() {
         let suffix = command.suffix.as_ref().unwrap();
@@ -573,31 +991,259 @@ fn eval_function(command: &mut SimpleCommand) -> Result<i32,&'static str> {
                 break;
             }
             //eprintln!("{}={}", i+1, arg);
-            shell::add_var_context(&format!("{}={}", i+1, arg));
+            shell::add_var_local(Var::new(&(i + 1).to_string(), arg));
         }
     }
 
     let mut function = function.unwrap().borrow().clone();
 
-    eval_compound_command(&mut function.compound_command);
+    let result = eval_compound_command(&mut function.compound_command);
     shell::pop_context();
 
-    Ok(0)//todo: change this to return the right exit code
+    // a return stops the function with the status that it was given
+    if matches!(shell::get_loop_skip(), Some((CommandExitStatus::Return, _))) {
+        shell::clear_loop_skip();
+        return Ok(get_exit_code());
+    }
+
+    match result {
+        Ok(status) => Ok(status),
+        Err(err) => {
+            eprintln!("{}", err);
+            Ok(1)
+        }
+    }
 }
 
-/// This function evaluates a CompoundCommand.
-fn eval_compound_command(command: &mut CompoundCommand) {
+/// This function evaluates a CompoundCommand and returns the exit status of the last command that it ran.
+fn eval_compound_command(command: &mut CompoundCommand) -> Result<i32,String> {
     match command {
         CompoundCommand::BraceGroup(bg) => {
-            eval_compound_list(&mut bg.0);
-        }
-        _ => unimplemented!(),
+            eval_compound_list(&mut bg.0)
+        },
+        CompoundCommand::IfClause(if_clause) => {
+            eval_if_clause(if_clause)
+        },
+        CompoundCommand::WhileClause(while_clause) => {
+            eval_loop(&mut while_clause.condition, &mut while_clause.do_group, false)
+        },
+        CompoundCommand::UntilClause(until_clause) => {
+            eval_loop(&mut until_clause.condition, &mut until_clause.do_group, true)
+        },
+        CompoundCommand::ForClause(for_clause) => {
+            eval_for_clause(for_clause)
+        },
+        CompoundCommand::CaseClause(case_clause) => {
+            eval_case_clause(case_clause)
+        },
+        CompoundCommand::ArithmeticCommand(arithmetic) => {
+            eval_arithmetic_command(arithmetic)
+        },
+        CompoundCommand::ConditionalCommand(conditional) => {
+            eval_conditional_command(conditional)
+        },
+        CompoundCommand::SubShell(subshell) => {
+            // a subshell is run the same way as a pipeline with nothing else in it
+            let mut pipeline = Pipeline {
+                bang: false,
+                pipe_sequence: PipeSequence(vec![Command::CompoundCommand(CompoundCommand::SubShell(subshell.clone()), None)]),
+                background: false,
+            };
+            eval_pipeline(&mut pipeline)
+        },
     }
 }
 
 /// This function evaluates a CompoundList.
-fn eval_compound_list(compound_list: &mut CompoundList) {
-    parse_tree(&mut compound_list.0.0);
+fn eval_compound_list(compound_list: &mut CompoundList) -> Result<i32,String> {
+    parse_tree(&mut compound_list.0.0)
+}
+
+/// This function evaluates an IfClause.
+/// The condition of the if and then every elif is run in order until one of them exits with a status of 0.
+/// The body of that branch is then run. If no condition succeeds the else branch is run if there is one.
+/// The exit status is the status of the branch that ran or 0 if no branch ran.
+fn eval_if_clause(if_clause: &mut IfClause) -> Result<i32,String> {
+    let status = eval_compound_list(&mut if_clause.condition)?;
+    if shell::get_loop_skip().is_some() {
+        return Ok(status);
+    }
+    if status == 0 {
+        return eval_compound_list(&mut if_clause.then);
+    }
+
+    for else_part in if_clause.else_part.iter_mut() {
+        match &mut else_part.condition {
+            Some(condition) => {
+                let status = eval_compound_list(condition)?;
+                if shell::get_loop_skip().is_some() {
+                    return Ok(status);
+                }
+                if status == 0 {
+                    return eval_compound_list(&mut else_part.then);
+                }
+            },
+            None => {
+                return eval_compound_list(&mut else_part.then);
+            }
+        }
+    }
+
+    Ok(0)
+}
+
+/// This function evaluates a while loop or an until loop.
+/// The body is run for as long as the condition exits with a status of 0 for a while loop
+/// or with a non zero status for an until loop.
+/// The exit status is the status of the last time the body ran or 0 if it never ran.
+fn eval_loop(condition: &mut CompoundList, do_group: &mut DoGroup, until: bool) -> Result<i32,String> {
+    shell::enter_loop();
+    let result = eval_loop_iterations(condition, do_group, until);
+    shell::exit_loop();
+    result
+}
+
+fn eval_loop_iterations(condition: &mut CompoundList, do_group: &mut DoGroup, until: bool) -> Result<i32,String> {
+    let mut status = 0;
+    loop {
+        // evaluation expands words in place so every iteration has to start from a fresh copy
+        let condition_status = eval_compound_list(&mut condition.clone())?;
+        match take_loop_skip() {
+            Some(CommandExitStatus::Break) => break,
+            Some(CommandExitStatus::Continue) => continue,
+            _ => {},
+        }
+        if (condition_status == 0) == until || was_interrupted(condition_status) {
+            break;
+        }
+
+        status = eval_compound_list(&mut do_group.0.clone())?;
+        match take_loop_skip() {
+            Some(CommandExitStatus::Break) => break,
+            Some(CommandExitStatus::Continue) => continue,
+            _ => {},
+        }
+        if was_interrupted(status) {
+            break;
+        }
+    }
+    Ok(status)
+}
+
+/// This function evaluates a for loop.
+/// The loop variable is set in the current context to each word of the list in turn before the body is run.
+/// Without a word list the loop goes over the positional parameters.
+/// The exit status is the status of the last time the body ran or 0 if it never ran.
+fn eval_for_clause(for_clause: &mut ForClause) -> Result<i32,String> {
+    let (name, words) = match &for_clause.for_type {
+        ForType::ForClauseReg(for_reg) => (for_reg.name.clone(), shell::get_positional_args()),
+        ForType::ForClauseList(for_list) => (for_list.name.clone(), for_list.word_list.expand()?),
+    };
+
+    shell::enter_loop();
+    let result = eval_for_iterations(&name, &words, &mut for_clause.do_group);
+    shell::exit_loop();
+    result
+}
+
+fn eval_for_iterations(name: &str, words: &[String], do_group: &mut DoGroup) -> Result<i32,String> {
+    let mut status = 0;
+    for word in words {
+        shell::add_var_context(&format!("{}={}", name, word));
+
+        status = eval_compound_list(&mut do_group.0.clone())?;
+        match take_loop_skip() {
+            Some(CommandExitStatus::Break) => break,
+            Some(CommandExitStatus::Continue) => continue,
+            _ => {},
+        }
+        if was_interrupted(status) {
+            break;
+        }
+    }
+    Ok(status)
+}
+
+/// This function evaluates a case statement.
+/// The word is expanded and matched against the patterns of every item in order.
+/// Only the body of the first item with a matching pattern is run.
+/// The exit status is the status of that body or 0 if no pattern matched.
+fn eval_case_clause(case_clause: &mut CaseClause) -> Result<i32,String> {
+    let word = expand::expand_word_single(&case_clause.word)?;
+    let case_list = match &mut case_clause.case_list {
+        Some(case_list) => case_list,
+        None => return Ok(0),
+    };
+
+    for case_item in case_list.0.iter_mut() {
+        if case_item.pattern.matches(&word)? {
+            return match &mut case_item.compound_list {
+                Some(compound_list) => eval_compound_list(compound_list),
+                None => Ok(0),
+            };
+        }
+    }
+    Ok(0)
+}
+
+/// This function evaluates an arithmetic command.
+/// The exit status is 0 if the expression is not 0 and 1 if it is 0 or could not be evaluated.
+fn eval_arithmetic_command(arithmetic: &ArithmeticCommand) -> Result<i32,String> {
+    match expand::expand_arithmetic(&arithmetic.0) {
+        Ok(0) => Ok(1),
+        Ok(_) => Ok(0),
+        Err(err) => {
+            eprintln!("rsh: {}", err);
+            Ok(1)
+        },
+    }
+}
+
+/// This function evaluates a conditional command.
+/// The exit status is 0 if the expression is true, 1 if it is false and 2 if it could not be evaluated.
+fn eval_conditional_command(conditional: &ConditionalCommand) -> Result<i32,String> {
+    match conditional.0.test() {
+        Ok(true) => Ok(0),
+        Ok(false) => Ok(1),
+        Err(err) => {
+            eprintln!("rsh: {}", err);
+            Ok(2)
+        },
+    }
+}
+
+/// This function consumes one level of a pending break or continue for the loop that is being evaluated.
+/// If the break or continue targets a loop further out, this loop has to stop so a Break is returned
+/// and the rest is left pending for the enclosing loops. A pending return or exit stops every loop as well,
+/// and is left for the function that it returns from or for the shell to exit.
+fn take_loop_skip() -> Option<CommandExitStatus> {
+    match shell::get_loop_skip() {
+        None => None,
+        Some((CommandExitStatus::Return | CommandExitStatus::ExitedWith(_), _)) => Some(CommandExitStatus::Break),
+        Some((skip, 1)) => {
+            shell::clear_loop_skip();
+            Some(skip)
+        },
+        Some((skip, count)) => {
+            shell::set_loop_skip(skip, count - 1);
+            Some(CommandExitStatus::Break)
+        },
+    }
+}
+
+/// This function gives the status of a pending exit, which a shell that is not interactive exits with
+/// once the command that it was evaluating has unwound.
+pub fn pending_exit() -> Option<i32> {
+    match shell::get_loop_skip() {
+        Some((CommandExitStatus::ExitedWith(status), _)) => Some(status),
+        _ => None,
+    }
+}
+
+/// This function checks if an exit status means that the command was killed by SIGINT.
+/// Loops stop when that happens so that Ctrl-C can get the user out of them.
+fn was_interrupted(status: i32) -> bool {
+    status == 128 + nix::sys::signal::Signal::SIGINT as i32
 }
 
 /// This function is a wraper for fork().
@@ -0,0 +1,637 @@
+use crate::arith;
+use crate::brace;
+use crate::context::{Key, Var};
+use crate::ast::{Assignment, AssignmentValue, Parameter, ParameterOperation, Subscript, Word, WordSegment};
+use crate::eval;
+use crate::glob;
+use crate::lexer;
+use crate::process::CommandExitStatus;
+use crate::shell;
+use nix::unistd::{getuid, User};
+
+/// A piece of a word after its quotes have been removed and its expansions have been done.
+#[derive(Debug,Clone,PartialEq)]
+enum Part {
+    /// Text of the word itself that was not quoted.
+    Literal(String),
+    /// Text that was inside quotes or escaped by a backslash.
+    Quoted(String),
+    /// The result of an unquoted parameter expansion, command substitution or arithmetic expansion,
+    /// which is split into fields at the characters of IFS.
+    Expansion(String),
+    /// The boundary between two elements of `$@` or `${name[@]}`, which always ends a field.
+    Break,
+}
+
+/// This function expands a word into the fields that it produces as an argument of a command.
+/// A word that expands to nothing and had no quotes in it produces no field at all.
+/// Brace expansion comes first and each of the words that it gives is expanded on its own.
+/// Fields with unquoted pattern characters are replaced by the paths that they match.
+pub fn expand_word(word: &Word) -> Result<Vec<String>,String> {
+    let mut words = Vec::new();
+    for word in brace::expand_braces(word) {
+        let fields = split_fields(expand_parts(&word.0, false)?, || lookup("IFS").unwrap_or_else(|| DEFAULT_IFS.to_string()));
+        words.append(&mut expand_paths(fields));
+    }
+    Ok(words)
+}
+
+/// This function expands a word into a single string without splitting it into fields,
+/// the way that the values of assignments and the targets of redirections are expanded.
+pub fn expand_word_single(word: &Word) -> Result<String,String> {
+    Ok(join_parts(expand_parts(&word.0, false)?))
+}
+
+/// This function expands a word that is used as a pattern, such as a case pattern.
+/// The quoted parts of the word are escaped so that they only match themselves.
+pub fn expand_pattern(word: &Word) -> Result<String,String> {
+    Ok(expand_parts(&word.0, false)?.into_iter().map(|part| match part {
+        Part::Literal(text) | Part::Expansion(text) => text,
+        Part::Quoted(text) => glob::escape(&text),
+        Part::Break => " ".to_string(),
+    }).collect())
+}
+
+/// This function expands a word that is used as a regular expression, as the one after `=~` in `[[ ]]`.
+/// The quoted parts of the word are escaped so that they only match themselves.
+pub fn expand_regex(word: &Word) -> Result<String,String> {
+    Ok(expand_parts(&word.0, false)?.into_iter().map(|part| match part {
+        Part::Literal(text) | Part::Expansion(text) => text,
+        Part::Quoted(text) => regex::escape(&text),
+        Part::Break => " ".to_string(),
+    }).collect())
+}
+
+/// This function evaluates the expression of a `$(( ))` expansion or a `(( ))` command.
+/// Parameter expansions and command substitutions in it are done before it is evaluated.
+pub fn expand_arithmetic(expression: &Word) -> Result<i64,String> {
+    arith::evaluate(&expand_string(&expression.0)?)
+}
+
+/// This function expands an assignment. The value of `name=value` is expanded to a single string, where besides
+/// at the start a tilde is also expanded after every `:` as in `PATH=~/bin:~/.cargo/bin`.
+/// The words of `name=(...)` are expanded like the arguments of a command, each to any number of elements.
+pub fn expand_assignment(assignment: &Assignment) -> Result<Assignment<String>,String> {
+    let index = match &assignment.index {
+        Some(index) => Some(expand_string(&index.0)?),
+        None => None,
+    };
+    let value = match &assignment.value {
+        AssignmentValue::Scalar(word) => AssignmentValue::Scalar(join_parts(expand_parts(&word.0, true)?)),
+        AssignmentValue::Array(words) => {
+            let mut values = Vec::new();
+            for word in words {
+                values.append(&mut expand_word(word)?);
+            }
+            AssignmentValue::Array(values)
+        },
+    };
+    Ok(Assignment { name: assignment.name.clone(), index, append: assignment.append, value })
+}
+
+/// This function turns the subscript of an array into the key of an element.
+/// The subscript of an associative array is the key as it is, while that of an indexed array
+/// is an arithmetic expression, whose value counts back from the end of the array if it is negative.
+pub fn array_key(name: &str, subscript: &str) -> Result<Key,String> {
+    let var = shell::get_var(name);
+    if var.as_ref().is_some_and(Var::is_associative) {
+        return Ok(Key::Name(subscript.to_string()));
+    }
+    let index = arith::evaluate(subscript)?;
+    if index >= 0 {
+        return Ok(Key::Index(index as usize));
+    }
+    let next = var.map_or(0, |var| var.next_index());
+    usize::try_from(next as i64 + index).map(Key::Index).map_err(|_| format!("{}[{}]: bad array subscript", name, subscript))
+}
+
+/// This function expands the body of a here-document the same way as the inside of double quotes.
+pub fn expand_here_document(segments: &[WordSegment]) -> Result<String,String> {
+    expand_string(segments)
+}
+
+fn join_parts(parts: Vec<Part>) -> String {
+    parts.into_iter().map(|part| match part {
+        Part::Literal(text) | Part::Quoted(text) | Part::Expansion(text) => text,
+        Part::Break => " ".to_string(),
+    }).collect()
+}
+
+/// This function removes the quotes from the segments of a word and does its tilde and parameter expansions,
+/// command substitutions and arithmetic expansions.
+/// The value of an assignment can have tildes after colons as well as at the start.
+fn expand_parts(segments: &[WordSegment], assignment: bool) -> Result<Vec<Part>,String> {
+    let mut parts = Vec::new();
+    for (index, segment) in segments.iter().enumerate() {
+        match segment {
+            WordSegment::Literal(text) => expand_literal(&mut parts, text, index == 0, index + 1 == segments.len(), assignment),
+            WordSegment::Escaped(chr) => parts.push(Part::Quoted(chr.to_string())),
+            WordSegment::SingleQuoted(text) => parts.push(Part::Quoted(text.clone())),
+            WordSegment::DoubleQuoted(inner) => parts.append(&mut expand_double_quoted(inner)?),
+            WordSegment::Parameter(parameter) => {
+                match expand_parameter(parameter)? {
+                    ParameterValue::Value(value) => parts.push(Part::Expansion(value)),
+                    ParameterValue::Fields(elements) => {
+                        for (index, element) in elements.into_iter().enumerate() {
+                            if index > 0 {
+                                parts.push(Part::Break);
+                            }
+                            parts.push(Part::Expansion(element));
+                        }
+                    },
+                    // the word keeps its quotes, while its unquoted text is split like the value of a variable
+                    ParameterValue::Word(word) => {
+                        parts.extend(expand_parts(&word.0, false)?.into_iter().map(|part| match part {
+                            Part::Literal(text) => Part::Expansion(text),
+                            part => part,
+                        }));
+                    },
+                }
+            },
+            expansion => parts.push(Part::Expansion(expand_segment(expansion)?)),
+        }
+    }
+    Ok(parts)
+}
+
+/// This function adds an unquoted literal to the parts of a word and expands the tilde prefixes in it.
+/// A tilde prefix is at the start of the word, or after a `:` in an assignment, and runs up to the first slash
+/// (or colon in an assignment). It is left as it is unless all of it is in this literal,
+/// so `~"user"` and `~$name` are not expanded. The literal is the end of the word if `last` is set.
+fn expand_literal(parts: &mut Vec<Part>, text: &str, word_start: bool, last: bool, assignment: bool) {
+    let mut rest = text;
+    let mut tilde_start = word_start;
+    loop {
+        if let Some(prefix) = rest.strip_prefix('~').filter(|_| tilde_start) {
+            let end = prefix.find(|chr| chr == '/' || (assignment && chr == ':'));
+            if end.is_some() || last {
+                let end = end.unwrap_or(prefix.len());
+                if let Some(directory) = expand_tilde(&prefix[..end]) {
+                    parts.push(Part::Quoted(directory));
+                    rest = &prefix[end..];
+                }
+            }
+        }
+        match rest.find(':').filter(|_| assignment) {
+            Some(index) => {
+                push_literal(parts, &rest[..=index]);
+                rest = &rest[index + 1..];
+                tilde_start = true;
+            },
+            None => {
+                push_literal(parts, rest);
+                return;
+            },
+        }
+    }
+}
+
+/// This function gives the directory that a tilde prefix stands for, without the `~`.
+/// An empty prefix is the home directory, `+` and `-` are the current and the previous working directory
+/// and anything else is the name of a user whose home directory is looked up in the passwd database.
+pub fn expand_tilde(prefix: &str) -> Option<String> {
+    match prefix {
+        "" => lookup("HOME").or_else(|| {
+            let user = User::from_uid(getuid()).ok()??;
+            Some(user.dir.to_string_lossy().into_owned())
+        }),
+        "+" => lookup("PWD"),
+        "-" => lookup("OLDPWD"),
+        name => {
+            let user = User::from_name(name).ok()??;
+            Some(user.dir.to_string_lossy().into_owned())
+        },
+    }
+}
+
+fn push_literal(parts: &mut Vec<Part>, text: &str) {
+    match parts.last_mut() {
+        Some(Part::Literal(literal)) => literal.push_str(text),
+        _ => parts.push(Part::Literal(text.to_string())),
+    }
+}
+
+/// This function expands the inside of double quotes, which gives a single field
+/// unless `"$@"` or `"${name[@]}"` is in it, which give a field for each element.
+/// The text in front of the first element and after the last one is joined to them,
+/// and there is no field at all if there are no elements and nothing else in the quotes.
+fn expand_double_quoted(segments: &[WordSegment]) -> Result<Vec<Part>,String> {
+    let mut parts = Vec::new();
+    let mut fields = false;
+    for segment in segments {
+        match segment {
+            WordSegment::Parameter(parameter) => {
+                match expand_parameter(parameter)? {
+                    ParameterValue::Fields(elements) => {
+                        fields = true;
+                        for (index, element) in elements.into_iter().enumerate() {
+                            if index > 0 {
+                                parts.push(Part::Break);
+                            }
+                            parts.push(Part::Quoted(element));
+                        }
+                    },
+                    value => parts.push(Part::Quoted(parameter_text(value)?)),
+                }
+            },
+            segment => parts.push(Part::Quoted(expand_segment(segment)?)),
+        }
+    }
+    if !fields && parts.is_empty() {
+        parts.push(Part::Quoted(String::new()));
+    }
+    Ok(parts)
+}
+
+/// This function expands segments into a single string with nothing removed or split,
+/// the way that the inside of double quotes and the body of a here-document are expanded.
+fn expand_string(segments: &[WordSegment]) -> Result<String,String> {
+    let mut text = String::new();
+    for segment in segments {
+        text.push_str(&expand_segment(segment)?);
+    }
+    Ok(text)
+}
+
+/// This function gives the text that a single segment expands to.
+fn expand_segment(segment: &WordSegment) -> Result<String,String> {
+    match segment {
+        WordSegment::Literal(text) | WordSegment::SingleQuoted(text) => Ok(text.clone()),
+        WordSegment::Escaped(chr) => Ok(chr.to_string()),
+        WordSegment::DoubleQuoted(inner) => expand_string(inner),
+        WordSegment::Parameter(parameter) => parameter_text(expand_parameter(parameter)?),
+        WordSegment::CommandSubstitution(command) => eval::command_substitution(command),
+        WordSegment::ProcessSubstitution(input, command) => eval::process_substitution(*input, command),
+        WordSegment::Arithmetic(expression) => Ok(expand_arithmetic(expression)?.to_string()),
+        WordSegment::BadSubstitution(text) => Err(format!("{}: bad substitution", text)),
+    }
+}
+
+/// This function looks up the value of a parameter.
+/// The special parameters are handled here and every other name goes to the shell's variables,
+/// which also resolves namespaced names like `ns::var`.
+fn lookup(name: &str) -> Option<String> {
+    match name {
+        "?" => Some(eval::get_exit_code().to_string()),
+        "#" => Some(shell::get_positional_args().len().to_string()),
+        name => shell::expand_var(name),
+    }
+}
+
+/// What a parameter expansion gives, which is either a value or the word of its operation
+/// that is expanded in its place, as the default word of `${name:-word}` is.
+/// `$@` and `${name[@]}` give the elements of the array, which are kept apart.
+enum ParameterValue<'a> {
+    Value(String),
+    Word(&'a Word),
+    Fields(Vec<String>),
+}
+
+/// This function gives the text of a parameter expansion that is not split into fields.
+fn parameter_text(value: ParameterValue) -> Result<String,String> {
+    match value {
+        ParameterValue::Value(value) => Ok(value),
+        ParameterValue::Word(word) => expand_word_single(word),
+        ParameterValue::Fields(elements) => Ok(elements.join(" ")),
+    }
+}
+
+/// This function expands a `$name` or `${...}` expansion and does its operation, if it has one.
+fn expand_parameter(parameter: &Parameter) -> Result<ParameterValue<'_>,String> {
+    let name = parameter.name.as_str();
+    if let Some((elements, joined)) = elements(parameter) {
+        return expand_elements(parameter, elements, joined);
+    }
+    let value = match &parameter.subscript {
+        Some(Subscript::Index(index)) => {
+            let key = array_key(name, &expand_string(&index.0)?)?;
+            shell::get_var(name).and_then(|var| var.element(&key).map(str::to_string))
+        },
+        _ => lookup(name),
+    };
+    let operation = match &parameter.operation {
+        Some(operation) => operation,
+        None => return Ok(ParameterValue::Value(value.unwrap_or_default())),
+    };
+    // with a colon an empty value counts the same as an unset one
+    let missing = |colon: bool| {
+        if colon {
+            value.as_deref().unwrap_or_default().is_empty()
+        }
+        else {
+            value.is_none()
+        }
+    };
+
+    let value = match operation {
+        ParameterOperation::Length => value.unwrap_or_default().chars().count().to_string(),
+        ParameterOperation::Keys => value.map_or(String::new(), |_| "0".to_string()),
+        ParameterOperation::Default(colon, word) => {
+            if missing(*colon) {
+                return Ok(ParameterValue::Word(word));
+            }
+            value.unwrap_or_default()
+        },
+        ParameterOperation::Assign(colon, word) => {
+            if !missing(*colon) {
+                return Ok(ParameterValue::Value(value.unwrap_or_default()));
+            }
+            if !name.split("::").all(lexer::is_name) {
+                return Err(format!("${}: cannot assign in this way", name));
+            }
+            let default = expand_word_single(word)?;
+            match &parameter.subscript {
+                Some(Subscript::Index(index)) => shell::set_array_element(name, &array_key(name, &expand_string(&index.0)?)?, &default, false),
+                _ => shell::add_var_context(&format!("{}={}", name, default)),
+            }
+            default
+        },
+        ParameterOperation::Error(colon, word) => {
+            if !missing(*colon) {
+                return Ok(ParameterValue::Value(value.unwrap_or_default()));
+            }
+            return Err(parameter_error(name, word));
+        },
+        ParameterOperation::Alternative(colon, word) => {
+            if !missing(*colon) {
+                return Ok(ParameterValue::Word(word));
+            }
+            String::new()
+        },
+        ParameterOperation::RemovePrefix(longest, word) => {
+            let operator = if *longest { "##" } else { "#" };
+            remove_pattern(&value.unwrap_or_default(), &expand_pattern(word)?, operator)
+        },
+        ParameterOperation::RemoveSuffix(longest, word) => {
+            let operator = if *longest { "%%" } else { "%" };
+            remove_pattern(&value.unwrap_or_default(), &expand_pattern(word)?, operator)
+        },
+    };
+    Ok(ParameterValue::Value(value))
+}
+
+/// This function gives the error of a `${name:?word}` expansion whose parameter is missing.
+/// It also records an exit with a status of 1 for the evaluator to unwind to, which the shell carries out
+/// when it is not interactive.
+fn parameter_error(name: &str, word: &Word) -> String {
+    shell::set_loop_skip(CommandExitStatus::ExitedWith(1), 1);
+    let message = if word.0.is_empty() {
+        "parameter null or not set".to_string()
+    }
+    else {
+        match expand_word_single(word) {
+            Ok(message) => message,
+            Err(err) => return err,
+        }
+    };
+    format!("{}: {}", name, message)
+}
+
+/// This function gives the elements that `$@`, `$*`, `${name[@]}` and `${name[*]}` expand to,
+/// or the indices or keys of the array for `${!name[@]}`, and whether the elements are joined into a single value.
+/// None is returned for any other parameter, which has a single value.
+fn elements(parameter: &Parameter) -> Option<(Vec<String>, bool)> {
+    let name = parameter.name.as_str();
+    let joined = match (&parameter.subscript, name) {
+        (Some(Subscript::All), _) | (None, "@") => false,
+        (Some(Subscript::Joined), _) | (None, "*") => true,
+        _ => return None,
+    };
+    let elements = match (parameter.subscript.is_some(), &parameter.operation, shell::get_var(name)) {
+        (false, ..) => shell::get_positional_args(),
+        (true, Some(ParameterOperation::Keys), Some(var)) => var.keys(),
+        (true, _, Some(var)) => var.elements(),
+        (true, _, None) => Vec::new(),
+    };
+    Some((elements, joined))
+}
+
+/// This function does the operation of an expansion of all the elements of an array or the positional parameters.
+/// The elements count as missing if there are none, or with a colon if they are all empty,
+/// and patterns are removed from each element on its own.
+fn expand_elements(parameter: &Parameter, elements: Vec<String>, joined: bool) -> Result<ParameterValue<'_>,String> {
+    let name = parameter.name.as_str();
+    let missing = |colon: bool| elements.is_empty() || (colon && elements.iter().all(String::is_empty));
+    let elements = match &parameter.operation {
+        None | Some(ParameterOperation::Keys) => elements,
+        Some(ParameterOperation::Length) => return Ok(ParameterValue::Value(elements.len().to_string())),
+        Some(ParameterOperation::Default(colon, word)) => {
+            if missing(*colon) {
+                return Ok(ParameterValue::Word(word));
+            }
+            elements
+        },
+        Some(ParameterOperation::Assign(colon, _)) => {
+            if missing(*colon) {
+                return Err(format!("${}: cannot assign in this way", name));
+            }
+            elements
+        },
+        Some(ParameterOperation::Error(colon, word)) => {
+            if missing(*colon) {
+                return Err(parameter_error(name, word));
+            }
+            elements
+        },
+        Some(ParameterOperation::Alternative(colon, word)) => {
+            if !missing(*colon) {
+                return Ok(ParameterValue::Word(word));
+            }
+            Vec::new()
+        },
+        Some(ParameterOperation::RemovePrefix(longest, word)) | Some(ParameterOperation::RemoveSuffix(longest, word)) => {
+            let prefix = matches!(parameter.operation, Some(ParameterOperation::RemovePrefix(..)));
+            let operator = match (prefix, *longest) {
+                (true, false) => "#",
+                (true, true) => "##",
+                (false, false) => "%",
+                (false, true) => "%%",
+            };
+            let pattern = expand_pattern(word)?;
+            elements.iter().map(|element| remove_pattern(element, &pattern, operator)).collect()
+        },
+    };
+    if joined {
+        let separator: String = lookup("IFS").map_or(" ".to_string(), |ifs| ifs.chars().take(1).collect());
+        return Ok(ParameterValue::Value(elements.join(&separator)));
+    }
+    Ok(ParameterValue::Fields(elements))
+}
+
+/// This function removes the shortest (`#`, `%`) or longest (`##`, `%%`) prefix (`#`) or suffix (`%`)
+/// of a value that matches a pattern. The value is left as it is if nothing matches.
+fn remove_pattern(value: &str, pattern: &str, operator: &str) -> String {
+    let bounds: Vec<usize> = value.char_indices().map(|(index, _)| index).chain(std::iter::once(value.len())).collect();
+    let prefix = |&&index: &&usize| glob::pattern_match(pattern, &value[..index]);
+    let suffix = |&&index: &&usize| glob::pattern_match(pattern, &value[index..]);
+    let result = match operator {
+        "#" => bounds.iter().find(prefix).map(|&index| &value[index..]),
+        "##" => bounds.iter().rev().find(prefix).map(|&index| &value[index..]),
+        "%" => bounds.iter().rev().find(suffix).map(|&index| &value[..index]),
+        _ => bounds.iter().find(suffix).map(|&index| &value[..index]),
+    };
+    result.unwrap_or(value).to_string()
+}
+
+/// A field of an expanded word along with the pattern it is matched against the filesystem with.
+#[derive(Debug,Default)]
+struct Field {
+    text: String,
+    /// The text with its quoted parts escaped.
+    pattern: String,
+    /// Set if an unquoted part of the field has a `*`, `?` or `[` in it.
+    glob: bool,
+}
+
+impl Field {
+    fn push_unquoted(&mut self, text: &str) {
+        self.text.push_str(text);
+        self.pattern.push_str(text);
+        self.glob |= text.contains(['*', '?', '[']);
+    }
+
+    fn push_quoted(&mut self, text: &str) {
+        self.text.push_str(text);
+        self.pattern.push_str(&glob::escape(text));
+    }
+}
+
+/// The characters that fields are split at when IFS is not set.
+const DEFAULT_IFS: &str = " \t\n";
+
+/// This function joins the parts of an expanded word into fields.
+/// Only the results of unquoted expansions are split, at the characters of IFS.
+/// A run of IFS whitespace separates fields and is ignored at the start and end of an expansion,
+/// while every other IFS character ends a field even if that leaves the field empty, as in `a::b`.
+/// IFS is only looked up once an expansion needs to be split.
+fn split_fields(parts: Vec<Part>, ifs: impl FnOnce() -> String) -> Vec<Field> {
+    let mut fields = Vec::new();
+    let mut field = Field::default();
+    // a field exists once it has text or quotes in it, so "" gives an empty field
+    let mut has_field = false;
+    // set when the last field was ended by IFS whitespace, which a following IFS character belongs to
+    let mut after_whitespace = false;
+    let mut ifs = Some(ifs);
+    let mut separators = String::new();
+    for part in parts {
+        match part {
+            Part::Literal(text) => {
+                if !text.is_empty() {
+                    has_field = true;
+                    after_whitespace = false;
+                }
+                field.push_unquoted(&text);
+            },
+            Part::Quoted(text) => {
+                has_field = true;
+                after_whitespace = false;
+                field.push_quoted(&text);
+            },
+            Part::Break => {
+                if has_field {
+                    fields.push(std::mem::take(&mut field));
+                    has_field = false;
+                }
+                after_whitespace = false;
+            },
+            Part::Expansion(text) => {
+                if let Some(ifs) = ifs.take() {
+                    separators = ifs();
+                }
+                for chr in text.chars() {
+                    if !separators.contains(chr) {
+                        let mut buffer = [0; 4];
+                        field.push_unquoted(chr.encode_utf8(&mut buffer));
+                        has_field = true;
+                        after_whitespace = false;
+                    }
+                    else if chr.is_whitespace() {
+                        if has_field {
+                            fields.push(std::mem::take(&mut field));
+                            has_field = false;
+                            after_whitespace = true;
+                        }
+                    }
+                    else if has_field || !after_whitespace {
+                        fields.push(std::mem::take(&mut field));
+                        has_field = false;
+                    }
+                    else {
+                        after_whitespace = false;
+                    }
+                }
+            },
+        }
+    }
+    if has_field {
+        fields.push(field);
+    }
+    fields
+}
+
+/// This function replaces the fields that are patterns with the sorted paths that match them.
+/// A pattern that matches nothing is left as it is, or removed if nullglob is set.
+/// Nothing is expanded if noglob is set.
+fn expand_paths(fields: Vec<Field>) -> Vec<String> {
+    if !fields.iter().any(|field| field.glob) || shell::get_option("noglob") {
+        return fields.into_iter().map(|field| field.text).collect();
+    }
+    let nullglob = shell::get_option("nullglob");
+    let dotglob = shell::get_option("dotglob");
+
+    let mut words = Vec::new();
+    for field in fields {
+        if !field.glob {
+            words.push(field.text);
+            continue;
+        }
+        let mut paths = glob::expand_path(&field.pattern, dotglob);
+        if paths.is_empty() && !nullglob {
+            words.push(field.text);
+        }
+        words.append(&mut paths);
+    }
+    words
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_remove_pattern() {
+        let path = "/usr/local/lib/file.tar.gz";
+        assert_eq!(remove_pattern(path, "*/", "#"), "usr/local/lib/file.tar.gz");
+        assert_eq!(remove_pattern(path, "*/", "##"), "file.tar.gz");
+        assert_eq!(remove_pattern(path, ".*", "%"), "/usr/local/lib/file.tar");
+        assert_eq!(remove_pattern(path, ".*", "%%"), "/usr/local/lib/file");
+        assert_eq!(remove_pattern(path, "x*", "#"), path);
+    }
+
+    #[test]
+    fn test_split_fields() {
+        let split = |parts: Vec<Part>, ifs: &str| -> Vec<String> {
+            split_fields(parts, || ifs.to_string()).into_iter().map(|field| field.text).collect()
+        };
+        let expansion = |text: &str| Part::Expansion(text.to_string());
+        assert_eq!(split(vec![expansion(" a  b ")], DEFAULT_IFS), vec!["a", "b"]);
+        assert_eq!(split(vec![Part::Literal("x".to_string()), expansion(" a "), Part::Literal("y".to_string())], DEFAULT_IFS), vec!["x", "a", "y"]);
+        assert_eq!(split(vec![expansion(":a::b:")], ":"), vec!["", "a", "", "b"]);
+        assert_eq!(split(vec![expansion("a : b")], " :"), vec!["a", "b"]);
+        assert_eq!(split(vec![expansion("a b")], ""), vec!["a b"]);
+        assert_eq!(split(vec![expansion(""), Part::Quoted(String::new())], DEFAULT_IFS), vec![""]);
+        assert!(split(vec![expansion("  ")], DEFAULT_IFS).is_empty());
+        assert_eq!(split(vec![Part::Quoted("a b".to_string()), Part::Break, Part::Quoted(String::new())], DEFAULT_IFS), vec!["a b", ""]);
+        assert_eq!(split(vec![expansion("a"), Part::Break, expansion(""), Part::Break, expansion("b")], DEFAULT_IFS), vec!["a", "b"]);
+    }
+
+    #[test]
+    fn test_quotes() {
+        let word = |text: &str| lexer::parse_word(text);
+        assert_eq!(expand_word(&word("'a b'\"c\"\\ d")).unwrap(), vec!["a bc d"]);
+        assert_eq!(expand_word(&word("\"\"")).unwrap(), vec![""]);
+        assert_eq!(expand_pattern(&word("'*'*")).unwrap(), "\\**");
+        assert_eq!(expand_word(&word("'~'/a")).unwrap(), vec!["~/a"]);
+        assert_eq!(expand_word(&word("\\~")).unwrap(), vec!["~"]);
+    }
+}
@@ -0,0 +1,883 @@
+use std::fmt::{self, Display, Formatter};
+use lalrpop_util::ParseError;
+use crate::ast::*;
+use crate::grammar;
+use crate::lexer::{self, Lexer, Token};
+
+/// What every level of nesting indents the lines of a command by.
+const INDENT: &str = "    ";
+
+/// This function formats a script in the canonical form that the printer gives commands:
+/// one command per line, four spaces of indentation for every level of nesting and no blanks between
+/// a redirection operator and its file. Comments are kept next to the commands they were next to
+/// and a run of blank lines becomes a single one. Parsing the result gives the same commands as the script.
+pub fn format(input: &str) -> Result<String, ParseError<usize, Token<'_>, lexer::Error>> {
+    let ast = grammar::CompleteCommandParser::new().parse(input, Lexer::new(input))?;
+    let mut printer = Printer::new(input);
+    printer.complete_command(&ast);
+    Ok(printer.finish())
+}
+
+/// This struct prints commands back to shell source.
+/// Given the source that the commands were parsed from, it puts the comments of the source back
+/// before the command that they came before, or at the end of the line they ended.
+pub struct Printer<'a> {
+    source: &'a str,
+    /// The comments of the source with where they start, which are printed in order.
+    comments: Vec<(usize, &'a str)>,
+    next_comment: usize,
+    /// Where the tokens of the source that are not separators start.
+    /// The `;;` of a case item is not a separator, so the comments after it go with the next item.
+    tokens: Vec<usize>,
+    output: String,
+    /// The line that is being printed, which is ended once something has to go on the next one.
+    line: Option<String>,
+    /// The bodies of the here-documents that were started on the current line with their delimiter lines.
+    here_documents: Vec<String>,
+    indent: usize,
+    /// Set at the start of a list, where a blank line is not kept.
+    list_start: bool,
+}
+
+impl<'a> Printer<'a> {
+    /// This function makes a printer for commands that were parsed from `source`.
+    /// Commands that do not come from any source are printed with an empty one.
+    pub fn new(source: &'a str) -> Self {
+        let mut lexer = Lexer::new(source);
+        let tokens = lexer.by_ref()
+            .filter_map(Result::ok)
+            .filter(|(_, token, _)| !matches!(token, Token::SemiColon | Token::NewlineList | Token::HereDoc(_) | Token::EOF))
+            .map(|(start, _, _)| start)
+            .collect();
+        Printer {
+            source,
+            comments: lexer.comments().to_vec(),
+            next_comment: 0,
+            tokens,
+            output: String::new(),
+            line: None,
+            here_documents: Vec::new(),
+            indent: 0,
+            list_start: true,
+        }
+    }
+
+    /// This function ends the last line and gives everything that was printed.
+    pub fn finish(mut self) -> String {
+        self.comments_before(usize::MAX);
+        self.end_line();
+        self.output
+    }
+
+    /// Adds text to the current line, starting a new one at the current indentation if there is none.
+    fn text(&mut self, text: &str) {
+        let indent = self.indent;
+        self.line.get_or_insert_with(|| INDENT.repeat(indent)).push_str(text);
+    }
+
+    /// Ends the current line, which is followed by the bodies of the here-documents that were started on it.
+    fn end_line(&mut self) {
+        if let Some(line) = self.line.take() {
+            self.output.push_str(&line);
+            self.output.push('\n');
+            for body in self.here_documents.drain(..) {
+                self.output.push_str(&body);
+            }
+        }
+    }
+
+    /// Keeps a blank line before what starts at `pos` if the line before it in the source is blank,
+    /// unless it starts a list.
+    fn blank_line(&mut self, pos: usize) {
+        let before = match self.source.get(..pos) {
+            Some(before) if !self.list_start => before,
+            _ => return,
+        };
+        let mut lines = before.rsplit('\n');
+        let current = lines.next().unwrap_or_default();
+        if current.trim().is_empty() && lines.next().is_some_and(|previous| previous.trim().is_empty()) {
+            self.end_line();
+            self.output.push('\n');
+        }
+    }
+
+    /// Prints the comments that start before `pos`.
+    fn comments_before(&mut self, pos: usize) {
+        while let Some(&(start, comment)) = self.comments.get(self.next_comment) {
+            if start >= pos {
+                break;
+            }
+            self.comment(start, comment);
+        }
+    }
+
+    /// Prints the comments that follow the end of a list at `end` with nothing but separators before them,
+    /// which belong to the list rather than to the reserved word after it.
+    fn comments_after(&mut self, end: usize) {
+        while let Some(&(start, comment)) = self.comments.get(self.next_comment) {
+            if !self.only_separators(end, start) {
+                break;
+            }
+            self.comment(start, comment);
+        }
+    }
+
+    /// This function checks if a comment is yet to be printed before `end`, or right after it.
+    fn has_comment_before(&self, end: usize) -> bool {
+        match self.comments.get(self.next_comment) {
+            Some(&(start, _)) => start < end || self.only_separators(end, start),
+            None => false,
+        }
+    }
+
+    /// This function checks if there is nothing but separators between `end` and `pos` in the source.
+    fn only_separators(&self, end: usize, pos: usize) -> bool {
+        let next = self.tokens.partition_point(|&start| start < end);
+        self.tokens.get(next).is_none_or(|&start| start >= pos)
+    }
+
+    /// Prints a comment at the end of the current line if it followed a command on its line in the source,
+    /// or on a line of its own otherwise.
+    fn comment(&mut self, start: usize, comment: &str) {
+        self.next_comment += 1;
+        let line_start = self.source[..start].rfind('\n').map_or(0, |newline| newline + 1);
+        let trailing = !self.source[line_start..start].trim().is_empty();
+        match &mut self.line {
+            Some(line) if trailing => {
+                line.push(' ');
+                line.push_str(comment.trim_end());
+            },
+            _ => {
+                self.end_line();
+                self.blank_line(start);
+                self.text(comment.trim_end());
+            },
+        }
+        self.end_line();
+        self.list_start = false;
+    }
+
+    /// Prints the commands of a list on lines of their own.
+    fn statements(&mut self, and_ors: &[AndOr]) {
+        for and_or in and_ors {
+            self.comments_before(and_or.span.start);
+            self.end_line();
+            self.blank_line(and_or.span.start);
+            self.and_or(and_or);
+            self.list_start = false;
+        }
+        if let Some(last) = and_ors.last() {
+            self.comments_after(last.span.end);
+        }
+    }
+
+    /// Prints the commands of a compound list one level deeper than the line before them.
+    fn block(&mut self, compound_list: &CompoundList) {
+        self.indent += 1;
+        self.list_start = true;
+        self.compound_list(compound_list);
+        self.indent -= 1;
+        self.end_line();
+    }
+
+    /// Prints the condition of `if`, `elif`, `while` or `until` and the reserved word that ends it.
+    /// A condition that is a single line of simple commands stays on the line of the reserved word before it.
+    fn condition(&mut self, condition: &CompoundList, end: &str) {
+        match condition.0.0.as_slice() {
+            [and_or] if is_simple(and_or) && !self.has_comment_before(and_or.span.end) => {
+                self.text(" ");
+                self.and_or(and_or);
+                self.text("; ");
+            },
+            _ => self.block(condition),
+        }
+        self.text(end);
+    }
+
+    pub fn complete_command(&mut self, complete_command: &CompleteCommand) {
+        if let Some(list) = &complete_command.list {
+            self.list(list);
+        }
+    }
+
+    pub fn list(&mut self, list: &List) {
+        self.statements(&list.0);
+    }
+
+    pub fn term(&mut self, term: &Term) {
+        self.statements(&term.0);
+    }
+
+    pub fn compound_list(&mut self, compound_list: &CompoundList) {
+        self.term(&compound_list.0);
+    }
+
+    pub fn and_or(&mut self, and_or: &AndOr) {
+        if let Some(previous) = &and_or.and_or {
+            self.and_or(previous);
+            self.text(" ");
+            self.conditional_exec(and_or.conditional_exec.as_ref().unwrap_or(&ConditionalExec::And));
+            self.text(" ");
+        }
+        self.pipeline(&and_or.pipeline);
+    }
+
+    pub fn conditional_exec(&mut self, conditional_exec: &ConditionalExec) {
+        self.text(match conditional_exec {
+            ConditionalExec::And => "&&",
+            ConditionalExec::Or => "||",
+        });
+    }
+
+    pub fn pipeline(&mut self, pipeline: &Pipeline) {
+        if pipeline.bang {
+            self.text("! ");
+        }
+        self.pipe_sequence(&pipeline.pipe_sequence);
+        if pipeline.background {
+            self.text(" &");
+        }
+    }
+
+    pub fn pipe_sequence(&mut self, pipe_sequence: &PipeSequence) {
+        for (index, command) in pipe_sequence.0.iter().enumerate() {
+            if index > 0 {
+                self.text(" | ");
+            }
+            self.command(command);
+        }
+    }
+
+    pub fn command(&mut self, command: &Command) {
+        match command {
+            Command::SimpleCommand(simple_command) => self.simple_command(simple_command),
+            Command::CompoundCommand(compound_command, redirect_list) => {
+                self.compound_command(compound_command);
+                if let Some(redirect_list) = redirect_list {
+                    self.text(" ");
+                    self.redirect_list(redirect_list);
+                }
+            },
+            Command::FunctionDefinition(function_definition) => self.function_definition(function_definition),
+        }
+    }
+
+    pub fn compound_command(&mut self, compound_command: &CompoundCommand) {
+        match compound_command {
+            CompoundCommand::BraceGroup(brace_group) => self.brace_group(brace_group),
+            CompoundCommand::SubShell(subshell) => self.subshell(subshell),
+            CompoundCommand::ForClause(for_clause) => self.for_clause(for_clause),
+            CompoundCommand::CaseClause(case_clause) => self.case_clause(case_clause),
+            CompoundCommand::IfClause(if_clause) => self.if_clause(if_clause),
+            CompoundCommand::WhileClause(while_clause) => self.while_clause(while_clause),
+            CompoundCommand::UntilClause(until_clause) => self.until_clause(until_clause),
+            CompoundCommand::ArithmeticCommand(arithmetic_command) => self.arithmetic_command(arithmetic_command),
+            CompoundCommand::ConditionalCommand(conditional_command) => self.conditional_command(conditional_command),
+        }
+    }
+
+    pub fn brace_group(&mut self, brace_group: &BraceGroup) {
+        self.text("{");
+        self.block(&brace_group.0);
+        self.text("}");
+    }
+
+    pub fn subshell(&mut self, subshell: &Subshell) {
+        self.text("(");
+        self.block(&subshell.compound_list);
+        self.text(")");
+    }
+
+    pub fn do_group(&mut self, do_group: &DoGroup) {
+        self.text("do");
+        self.block(&do_group.0);
+        self.text("done");
+    }
+
+    pub fn for_clause(&mut self, for_clause: &ForClause) {
+        self.for_type(&for_clause.for_type);
+        self.text("; ");
+        self.do_group(&for_clause.do_group);
+    }
+
+    pub fn for_type(&mut self, for_type: &ForType) {
+        match for_type {
+            ForType::ForClauseReg(for_clause_reg) => {
+                self.text("for ");
+                self.text(&for_clause_reg.name);
+            },
+            ForType::ForClauseList(for_clause_list) => {
+                self.text("for ");
+                self.text(&for_clause_list.name);
+                self.text(" in");
+                for word in &for_clause_list.word_list.0 {
+                    self.text(" ");
+                    self.word(word);
+                }
+            },
+        }
+    }
+
+    pub fn word_list(&mut self, word_list: &WordList) {
+        for (index, word) in word_list.0.iter().enumerate() {
+            if index > 0 {
+                self.text(" ");
+            }
+            self.word(word);
+        }
+    }
+
+    pub fn case_clause(&mut self, case_clause: &CaseClause) {
+        self.text("case ");
+        self.word(&case_clause.word);
+        self.text(" in");
+        if let Some(case_list) = &case_clause.case_list {
+            self.indent += 1;
+            self.list_start = true;
+            self.case_list(case_list);
+            self.indent -= 1;
+        }
+        self.end_line();
+        self.text("esac");
+    }
+
+    pub fn case_list(&mut self, case_list: &CaseList) {
+        for case_item in &case_list.0 {
+            self.comments_before(case_item.span.start);
+            self.end_line();
+            self.blank_line(case_item.span.start);
+            self.case_item(case_item);
+            self.list_start = false;
+        }
+        if let Some(last) = case_list.0.last() {
+            self.comments_after(last.span.end);
+        }
+    }
+
+    /// Prints a case item with its commands and `;;` one level deeper than its pattern.
+    pub fn case_item(&mut self, case_item: &CaseItem) {
+        self.pattern(&case_item.pattern);
+        self.text(")");
+        self.indent += 1;
+        if let Some(compound_list) = &case_item.compound_list {
+            self.list_start = true;
+            self.compound_list(compound_list);
+        }
+        self.end_line();
+        self.text(";;");
+        self.indent -= 1;
+    }
+
+    pub fn pattern(&mut self, pattern: &Pattern) {
+        for (index, word) in pattern.0.iter().enumerate() {
+            if index > 0 {
+                self.text(" | ");
+            }
+            self.word(word);
+        }
+    }
+
+    pub fn if_clause(&mut self, if_clause: &IfClause) {
+        self.text("if");
+        self.condition(&if_clause.condition, "then");
+        self.block(&if_clause.then);
+        for else_part in &if_clause.else_part {
+            self.else_part(else_part);
+        }
+        self.text("fi");
+    }
+
+    pub fn else_part(&mut self, else_part: &ElsePart) {
+        match &else_part.condition {
+            Some(condition) => {
+                self.text("elif");
+                self.condition(condition, "then");
+            },
+            None => self.text("else"),
+        }
+        self.block(&else_part.then);
+    }
+
+    pub fn while_clause(&mut self, while_clause: &WhileClause) {
+        self.text("while");
+        self.condition(&while_clause.condition, "");
+        self.do_group(&while_clause.do_group);
+    }
+
+    pub fn until_clause(&mut self, until_clause: &UntilClause) {
+        self.text("until");
+        self.condition(&until_clause.condition, "");
+        self.do_group(&until_clause.do_group);
+    }
+
+    pub fn function_definition(&mut self, function_definition: &FunctionDefinition) {
+        self.text(&function_definition.name);
+        self.text("() ");
+        self.function_body(&function_definition.function_body);
+    }
+
+    pub fn function_body(&mut self, function_body: &FunctionBody) {
+        self.compound_command(&function_body.compound_command);
+        if let Some(redirect_list) = &function_body.redirect_list {
+            self.text(" ");
+            self.redirect_list(redirect_list);
+        }
+    }
+
+    pub fn arithmetic_command(&mut self, arithmetic_command: &ArithmeticCommand) {
+        self.text(&format!("(({}))", segments_text(&arithmetic_command.0.0, false)));
+    }
+
+    pub fn conditional_command(&mut self, conditional_command: &ConditionalCommand) {
+        self.text(&format!("[[ {} ]]", conditional_text(&conditional_command.0)));
+    }
+
+    pub fn conditional_expression(&mut self, conditional_expression: &ConditionalExpression) {
+        self.text(&conditional_text(conditional_expression));
+    }
+
+    /// Prints a simple command with its assignments and redirections before its name and its words
+    /// before its redirections after it, which is the order that the parser keeps them in.
+    pub fn simple_command(&mut self, simple_command: &SimpleCommand) {
+        let mut blank = false;
+        if let Some(prefix) = &simple_command.prefix {
+            self.prefix(prefix);
+            blank = true;
+        }
+        if !simple_command.name.0.is_empty() {
+            if blank {
+                self.text(" ");
+            }
+            self.word(&simple_command.name);
+        }
+        if let Some(suffix) = &simple_command.suffix {
+            self.text(" ");
+            self.suffix(suffix);
+        }
+    }
+
+    pub fn prefix(&mut self, prefix: &Prefix) {
+        let mut blank = false;
+        for assignment in &prefix.assignment {
+            if blank {
+                self.text(" ");
+            }
+            self.assignment(assignment);
+            blank = true;
+        }
+        for io_redirect in &prefix.io_redirect {
+            if blank {
+                self.text(" ");
+            }
+            self.io_redirect(io_redirect);
+            blank = true;
+        }
+    }
+
+    pub fn suffix(&mut self, suffix: &Suffix) {
+        let mut blank = false;
+        for word in &suffix.word {
+            if blank {
+                self.text(" ");
+            }
+            self.word(word);
+            blank = true;
+        }
+        for io_redirect in &suffix.io_redirect {
+            if blank {
+                self.text(" ");
+            }
+            self.io_redirect(io_redirect);
+            blank = true;
+        }
+    }
+
+    pub fn assignment(&mut self, assignment: &Assignment) {
+        self.text(&assignment.name);
+        if let Some(index) = &assignment.index {
+            self.text(&format!("[{}]", segments_text(&index.0, false)));
+        }
+        self.text(if assignment.append { "+=" } else { "=" });
+        self.assignment_value(&assignment.value);
+    }
+
+    pub fn assignment_value(&mut self, assignment_value: &AssignmentValue) {
+        match assignment_value {
+            AssignmentValue::Scalar(word) => self.word(word),
+            AssignmentValue::Array(words) => {
+                self.text("(");
+                self.word_list(&WordList(words.clone()));
+                self.text(")");
+            },
+        }
+    }
+
+    pub fn redirect_list(&mut self, redirect_list: &RedirectList) {
+        for (index, io_redirect) in redirect_list.0.iter().enumerate() {
+            if index > 0 {
+                self.text(" ");
+            }
+            self.io_redirect(io_redirect);
+        }
+    }
+
+    pub fn io_redirect(&mut self, io_redirect: &IoRedirect) {
+        if let Some(io_number) = io_redirect.io_number {
+            self.text(&io_number.to_string());
+        }
+        if let Some(io_file) = &io_redirect.io_file {
+            self.io_file(io_file);
+        }
+        if let Some(io_here) = &io_redirect.io_here {
+            self.io_here(io_here);
+        }
+    }
+
+    /// Prints a redirection to a file with no blank after its operator,
+    /// unless the file is a process substitution that would make the operator a different one.
+    pub fn io_file(&mut self, io_file: &IoFile) {
+        self.redirect_type(&io_file.redirect_type);
+        if let Some(WordSegment::ProcessSubstitution(..)) = io_file.filename.0.first() {
+            self.text(" ");
+        }
+        self.word(&io_file.filename);
+    }
+
+    pub fn redirect_type(&mut self, redirect_type: &RedirectType) {
+        self.text(match redirect_type {
+            RedirectType::Input => "<",
+            RedirectType::Output => ">",
+            RedirectType::Append => ">>",
+            RedirectType::Clobber => ">|",
+            RedirectType::ReadWrite => "<>",
+            RedirectType::DuplicateInput => "<&",
+            RedirectType::DuplicateOutput => ">&",
+        });
+    }
+
+    /// Prints the operator and delimiter of a here-document, whose body follows the line that they end up on.
+    /// The delimiter is kept as it was written, so the body ends where it did and is expanded the same way.
+    pub fn io_here(&mut self, io_here: &IoHere) {
+        self.text(if io_here.strip_tabs { "<<-" } else { "<<" });
+        self.text(&io_here.delimiter);
+        let mut body = io_here.here.clone();
+        if !body.is_empty() && !body.ends_with('\n') {
+            body.push('\n');
+        }
+        body.push_str(&lexer::unquote_delimiter(&io_here.delimiter));
+        body.push('\n');
+        self.here_documents.push(body);
+    }
+
+    pub fn word(&mut self, word: &Word) {
+        self.text(&segments_text(&word.0, false));
+    }
+}
+
+/// This function checks if a command of a list is made of simple commands only.
+fn is_simple(and_or: &AndOr) -> bool {
+    and_or.pipeline.pipe_sequence.0.iter().all(|command| matches!(command, Command::SimpleCommand(_)))
+        && and_or.and_or.as_deref().is_none_or(is_simple)
+}
+
+/// This function gives the source of the segments of a word, which are inside double quotes if `quoted` is set.
+/// Every segment is written the way the parser reads it back as the same segment in the same place.
+fn segments_text(segments: &[WordSegment], quoted: bool) -> String {
+    let mut text = String::new();
+    for (index, segment) in segments.iter().enumerate() {
+        match segment {
+            WordSegment::Literal(literal) if quoted => {
+                for chr in literal.chars() {
+                    if matches!(chr, '$' | '`' | '"' | '\\') {
+                        text.push('\\');
+                    }
+                    text.push(chr);
+                }
+            },
+            WordSegment::Literal(literal) => text.push_str(literal),
+            WordSegment::Escaped(chr) => {
+                text.push('\\');
+                text.push(*chr);
+            },
+            WordSegment::SingleQuoted(string) => text.push_str(&format!("'{}'", string)),
+            WordSegment::DoubleQuoted(inner) => text.push_str(&format!("\"{}\"", segments_text(inner, true))),
+            WordSegment::Parameter(parameter) => {
+                let next = match segments.get(index + 1) {
+                    Some(WordSegment::Literal(literal)) => literal.chars().next(),
+                    _ => None,
+                };
+                text.push_str(&parameter_text(parameter, next));
+            },
+            WordSegment::CommandSubstitution(command) => text.push_str(&command_substitution_text(command, quoted)),
+            WordSegment::ProcessSubstitution(input, command) => {
+                text.push_str(&format!("{}({})", if *input { '<' } else { '>' }, command));
+            },
+            WordSegment::Arithmetic(expression) => text.push_str(&format!("$(({}))", segments_text(&expression.0, false))),
+            WordSegment::BadSubstitution(substitution) => text.push_str(substitution),
+        }
+    }
+    text
+}
+
+/// This function gives the source of a parameter expansion followed by the character `next`.
+/// It is written as `$name` unless it has a subscript or an operation, or `next` would become part of its name.
+fn parameter_text(parameter: &Parameter, next: Option<char>) -> String {
+    let name = &parameter.name;
+    let special = name.len() == 1 && name.starts_with(|chr: char| chr.is_ascii_digit() || matches!(chr, '?' | '#' | '@' | '*'));
+    let continues = next.is_some_and(|chr| chr.is_ascii_alphanumeric() || chr == '_' || chr == ':');
+    let plain = name.starts_with(|chr: char| chr.is_ascii_alphabetic() || chr == '_') && !continues;
+    if parameter.subscript.is_none() && parameter.operation.is_none() && (special || plain) {
+        return format!("${}", name);
+    }
+
+    let mut text = String::from("${");
+    match parameter.operation {
+        Some(ParameterOperation::Length) => text.push('#'),
+        Some(ParameterOperation::Keys) => text.push('!'),
+        _ => {},
+    }
+    text.push_str(name);
+    match &parameter.subscript {
+        Some(Subscript::All) => text.push_str("[@]"),
+        Some(Subscript::Joined) => text.push_str("[*]"),
+        Some(Subscript::Index(index)) => text.push_str(&format!("[{}]", segments_text(&index.0, false))),
+        None => {},
+    }
+    let (operator, word) = match &parameter.operation {
+        Some(ParameterOperation::Default(colon, word)) => (if *colon { ":-" } else { "-" }, word),
+        Some(ParameterOperation::Assign(colon, word)) => (if *colon { ":=" } else { "=" }, word),
+        Some(ParameterOperation::Error(colon, word)) => (if *colon { ":?" } else { "?" }, word),
+        Some(ParameterOperation::Alternative(colon, word)) => (if *colon { ":+" } else { "+" }, word),
+        Some(ParameterOperation::RemovePrefix(longest, word)) => (if *longest { "##" } else { "#" }, word),
+        Some(ParameterOperation::RemoveSuffix(longest, word)) => (if *longest { "%%" } else { "%" }, word),
+        _ => ("", &Word(Vec::new())),
+    };
+    text.push_str(operator);
+    text.push_str(&segments_text(&word.0, false));
+    text.push('}');
+    text
+}
+
+/// This function gives the source of a command substitution, which is `$(...)` unless the command
+/// would not be read back the same way in it, as when it starts with `(` and would make it `$((`.
+/// Backquotes are used then, with backslashes before the characters that they would unescape.
+fn command_substitution_text(command: &str, quoted: bool) -> String {
+    if !command.starts_with('(') && lexer::command_end(&format!("{})", command)) == Some(command.len()) {
+        return format!("$({})", command);
+    }
+    let mut text = String::from("`");
+    for chr in command.chars() {
+        if matches!(chr, '$' | '`' | '\\') || (chr == '"' && quoted) {
+            text.push('\\');
+        }
+        text.push(chr);
+    }
+    text.push('`');
+    text
+}
+
+/// This function gives the source of a `[[ ]]` expression, with parentheses only where
+/// the operators would group it differently without them.
+fn conditional_text(expression: &ConditionalExpression) -> String {
+    // how tightly an expression binds, so that an operand that binds more loosely than its operator is grouped
+    fn precedence(expression: &ConditionalExpression) -> u8 {
+        match expression {
+            ConditionalExpression::Or(..) => 0,
+            ConditionalExpression::And(..) => 1,
+            _ => 2,
+        }
+    }
+    let operand = |operand: &ConditionalExpression, tightest: u8| {
+        if precedence(operand) < tightest {
+            format!("( {} )", conditional_text(operand))
+        }
+        else {
+            conditional_text(operand)
+        }
+    };
+    match expression {
+        ConditionalExpression::Or(lhs, rhs) => format!("{} || {}", operand(lhs, 0), operand(rhs, 1)),
+        ConditionalExpression::And(lhs, rhs) => format!("{} && {}", operand(lhs, 1), operand(rhs, 2)),
+        ConditionalExpression::Not(inner) => format!("! {}", operand(inner, 2)),
+        ConditionalExpression::Unary(operator, word) => format!("{} {}", operator, segments_text(&word.0, false)),
+        ConditionalExpression::Binary(lhs, operator, rhs) => {
+            format!("{} {} {}", segments_text(&lhs.0, false), operator, segments_text(&rhs.0, false))
+        },
+        ConditionalExpression::Word(word) => segments_text(&word.0, false),
+    }
+}
+
+/// This macro implements Display for AST nodes with the method of the printer that prints them.
+/// They are printed without a source, so without comments, and without the newline that ends their last line.
+macro_rules! display {
+    ($($node:ty => $method:ident,)*) => {
+        $(
+            impl Display for $node {
+                fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+                    let mut printer = Printer::new("");
+                    printer.$method(self);
+                    f.write_str(printer.finish().trim_end_matches('\n'))
+                }
+            }
+        )*
+    };
+}
+
+display! {
+    CompleteCommand => complete_command,
+    List => list,
+    AndOr => and_or,
+    ConditionalExec => conditional_exec,
+    Pipeline => pipeline,
+    PipeSequence => pipe_sequence,
+    Command => command,
+    CompoundCommand => compound_command,
+    Subshell => subshell,
+    CompoundList => compound_list,
+    Term => term,
+    ForType => for_type,
+    ForClause => for_clause,
+    WordList => word_list,
+    CaseClause => case_clause,
+    CaseList => case_list,
+    CaseItem => case_item,
+    Pattern => pattern,
+    IfClause => if_clause,
+    ElsePart => else_part,
+    WhileClause => while_clause,
+    UntilClause => until_clause,
+    FunctionDefinition => function_definition,
+    FunctionBody => function_body,
+    BraceGroup => brace_group,
+    DoGroup => do_group,
+    ArithmeticCommand => arithmetic_command,
+    ConditionalCommand => conditional_command,
+    ConditionalExpression => conditional_expression,
+    SimpleCommand => simple_command,
+    Prefix => prefix,
+    Assignment => assignment,
+    AssignmentValue => assignment_value,
+    Suffix => suffix,
+    RedirectList => redirect_list,
+    IoRedirect => io_redirect,
+    RedirectType => redirect_type,
+    IoFile => io_file,
+    IoHere => io_here,
+    Word => word,
+}
+
+impl Display for WordSegment {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.write_str(&segments_text(std::slice::from_ref(self), false))
+    }
+}
+
+impl Display for Parameter {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.write_str(&parameter_text(self, None))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn parse(input: &str) -> CompleteCommand {
+        grammar::CompleteCommandParser::new().parse(input, Lexer::new(input)).unwrap()
+    }
+
+    /// This function resets the spans of an AST, which differ between an input and its formatted version.
+    fn without_spans(mut ast: CompleteCommand) -> CompleteCommand {
+        if let Some(list) = &mut ast.list {
+            list.0.iter_mut().for_each(reset_and_or);
+        }
+        ast
+    }
+
+    fn reset_and_or(and_or: &mut AndOr) {
+        and_or.span = Span::default();
+        if let Some(and_or) = &mut and_or.and_or {
+            reset_and_or(and_or);
+        }
+        for command in and_or.pipeline.pipe_sequence.0.iter_mut() {
+            match command {
+                Command::SimpleCommand(_) => {},
+                Command::CompoundCommand(compound_command, _) => reset_compound_command(compound_command),
+                Command::FunctionDefinition(definition) => reset_compound_command(&mut definition.function_body.compound_command),
+            }
+        }
+    }
+
+    fn reset_compound_list(compound_list: &mut CompoundList) {
+        compound_list.0.0.iter_mut().for_each(reset_and_or);
+    }
+
+    fn reset_compound_command(compound_command: &mut CompoundCommand) {
+        match compound_command {
+            CompoundCommand::BraceGroup(BraceGroup(compound_list)) | CompoundCommand::SubShell(Subshell { compound_list }) => {
+                reset_compound_list(compound_list);
+            },
+            CompoundCommand::ForClause(ForClause { do_group, .. }) => reset_compound_list(&mut do_group.0),
+            CompoundCommand::WhileClause(WhileClause { condition, do_group }) | CompoundCommand::UntilClause(UntilClause { condition, do_group }) => {
+                reset_compound_list(condition);
+                reset_compound_list(&mut do_group.0);
+            },
+            CompoundCommand::IfClause(if_clause) => {
+                reset_compound_list(&mut if_clause.condition);
+                reset_compound_list(&mut if_clause.then);
+                for else_part in if_clause.else_part.iter_mut() {
+                    else_part.condition.iter_mut().for_each(reset_compound_list);
+                    reset_compound_list(&mut else_part.then);
+                }
+            },
+            CompoundCommand::CaseClause(case_clause) => {
+                for case_item in case_clause.case_list.iter_mut().flat_map(|case_list| case_list.0.iter_mut()) {
+                    case_item.span = Span::default();
+                    case_item.compound_list.iter_mut().for_each(reset_compound_list);
+                }
+            },
+            CompoundCommand::ArithmeticCommand(_) | CompoundCommand::ConditionalCommand(_) => {},
+        }
+    }
+
+    #[test]
+    fn test_format() {
+        let input = "#!/bin/sh\n# set up\n\nx=1   y=\"a $b\"  # trailing\nif [ \"$x\" = 1 ] ;then echo yes\nelif a &&\n b # why\nthen :\nelse\n  # none\n  echo no >&2\nfi\n";
+        let expected = "#!/bin/sh\n# set up\n\nx=1 y=\"a $b\" # trailing\nif [ \"$x\" = 1 ]; then\n    echo yes\nelif\n    a && b # why\nthen\n    :\nelse\n    # none\n    echo no >&2\nfi\n";
+        assert_eq!(format(input).unwrap(), expected);
+        assert_eq!(format("case $x in (a|b) f;; *) esac").unwrap(), "case $x in\n    a | b)\n        f\n        ;;\n    *)\n        ;;\nesac\n");
+        assert_eq!(format("cat <<END | grep EOF\nEOF\nEND\n").unwrap(), "cat <<END | grep EOF\nEOF\nEND\n");
+        assert_eq!(format("cat <<\"E\"N\\D; cat <<-'X'\n$a\nEND\n\tX\n").unwrap(), "cat <<\"E\"N\\D\n$a\nEND\ncat <<-'X'\nX\n");
+        assert_eq!(format("echo a \\\n  b; a=(x \\\n y)").unwrap(), "echo a b\na=(x y)\n");
+        assert_eq!(format("a & b; { c & } &").unwrap(), "a &\nb\n{\n    c &\n} &\n");
+    }
+
+    #[test]
+    fn test_format_round_trip() {
+        let inputs = [
+            "for i in 1 2; do echo $i; done; for j do :; done\nwhile read l <<'X'; do echo \"$l\"; done >out\nline\nX\n",
+            "until false; do break; done &\n! a | b || c\nf() { local v=${1:-'d e'}; echo ${v}x ${#v} \"${a[@]}\" ${!a[*]} ${a[i+1]} ${p##*/} $10 ${10}; }",
+            "echo \"a\\$b \\\"q\\\" \\\\ `x`\" `(sub)` $(echo $((1 + (2))) \"$(case a in a) echo ;; esac)\")",
+            "a[2]+=x b=(1 'two' \"$c\") 2>&1 cmd arg >o <i 3<&- >>p <>rw\necho <(ls) > >(cat); cat < <(ls)",
+            "[[ -n $x && ( $x == a* || $x =~ ^([0-9]+)$ ) && ! ( -f f || a < b ) ]]; (( x += 1 ))\n( (a) ) >log",
+            "cat <<-EOF\n\tbody $x\n\tEOF\n{ a; b; } 2>/dev/null &\ng() ( echo ) >f\nif (a); then b; fi",
+        ];
+        for input in inputs {
+            let formatted = format(input).unwrap();
+            println!("{}", formatted);
+            assert_eq!(without_spans(parse(&formatted)), without_spans(parse(input)));
+            assert_eq!(format(&formatted).unwrap(), formatted);
+        }
+    }
+
+    #[test]
+    fn test_display() {
+        let ast = parse("echo ${a}b >&2 <<EOF\n$x\nEOF\n");
+        let list = ast.list.unwrap();
+        assert_eq!(list.0[0].to_string(), "echo ${a}b >&2 <<EOF\n$x\nEOF");
+        match &list.0[0].pipeline.pipe_sequence.0[0] {
+            Command::SimpleCommand(simple_command) => assert_eq!(simple_command.suffix.as_ref().unwrap().word[0].to_string(), "${a}b"),
+            command => panic!("not a simple command: {:?}", command),
+        }
+    }
+}
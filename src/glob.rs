@@ -0,0 +1,296 @@
+use std::fs;
+use std::path::Path;
+
+/// This function checks if a string matches a shell pattern.
+/// `*` matches any string, `?` matches any single character and `[...]` matches a single character
+/// out of a set, which can hold ranges like `a-z`, classes like `[:digit:]` and be negated with `!` or `^`.
+/// A backslash makes the character after it match literally.
+pub fn pattern_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+
+    let mut p = 0;
+    let mut t = 0;
+    // the position of the last star in the pattern and the position in the text it is matched up to
+    let mut star: Option<(usize, usize)> = None;
+
+    while t < text.len() {
+        if p < pattern.len() {
+            match pattern[p] {
+                '*' => {
+                    star = Some((p, t));
+                    p += 1;
+                    continue;
+                },
+                '?' => {
+                    p += 1;
+                    t += 1;
+                    continue;
+                },
+                '[' => {
+                    match match_bracket(&pattern[p..], text[t]) {
+                        Some((true, len)) => {
+                            p += len;
+                            t += 1;
+                            continue;
+                        },
+                        Some((false, _)) => {},
+                        None => {
+                            if text[t] == '[' {
+                                p += 1;
+                                t += 1;
+                                continue;
+                            }
+                        },
+                    }
+                },
+                '\\' if p + 1 < pattern.len() => {
+                    if pattern[p + 1] == text[t] {
+                        p += 2;
+                        t += 1;
+                        continue;
+                    }
+                },
+                chr => {
+                    if chr == text[t] {
+                        p += 1;
+                        t += 1;
+                        continue;
+                    }
+                },
+            }
+        }
+
+        // on a mismatch we let the last star swallow one more character and try again
+        match star {
+            Some((star_p, star_t)) => {
+                p = star_p + 1;
+                t = star_t + 1;
+                star = Some((star_p, star_t + 1));
+            },
+            None => return false,
+        }
+    }
+
+    while p < pattern.len() && pattern[p] == '*' {
+        p += 1;
+    }
+    p == pattern.len()
+}
+
+/// This function matches a single character against a bracket expression at the start of the pattern.
+/// It returns whether the character matched and the length of the bracket expression.
+/// None is returned if the bracket is never closed, in which case the `[` is an ordinary character.
+fn match_bracket(pattern: &[char], chr: char) -> Option<(bool, usize)> {
+    let mut i = 1;
+    let negate = i < pattern.len() && (pattern[i] == '!' || pattern[i] == '^');
+    if negate {
+        i += 1;
+    }
+
+    let mut matched = false;
+    let mut first = true;
+    while i < pattern.len() {
+        let mut low = pattern[i];
+        if low == ']' && !first {
+            return Some((matched != negate, i + 1));
+        }
+        first = false;
+
+        if low == '[' && i + 1 < pattern.len() && pattern[i + 1] == ':' {
+            if let Some(end) = (i + 2..pattern.len() - 1).find(|&j| pattern[j] == ':' && pattern[j + 1] == ']') {
+                let class: String = pattern[i + 2..end].iter().collect();
+                if class_match(&class, chr) {
+                    matched = true;
+                }
+                i = end + 2;
+                continue;
+            }
+        }
+
+        if low == '\\' && i + 1 < pattern.len() {
+            i += 1;
+            low = pattern[i];
+        }
+
+        if i + 2 < pattern.len() && pattern[i + 1] == '-' && pattern[i + 2] != ']' {
+            let mut high = pattern[i + 2];
+            let mut len = 3;
+            if high == '\\' && i + 3 < pattern.len() {
+                high = pattern[i + 3];
+                len = 4;
+            }
+            if low <= chr && chr <= high {
+                matched = true;
+            }
+            i += len;
+            continue;
+        }
+
+        if low == chr {
+            matched = true;
+        }
+        i += 1;
+    }
+
+    None
+}
+
+/// This function checks a character against a named character class such as `alpha` in `[[:alpha:]]`.
+fn class_match(class: &str, chr: char) -> bool {
+    match class {
+        "alnum" => chr.is_alphanumeric(),
+        "alpha" => chr.is_alphabetic(),
+        "blank" => chr == ' ' || chr == '\t',
+        "cntrl" => chr.is_control(),
+        "digit" => chr.is_ascii_digit(),
+        "graph" => chr.is_ascii_graphic(),
+        "lower" => chr.is_lowercase(),
+        "print" => chr.is_ascii_graphic() || chr == ' ',
+        "punct" => chr.is_ascii_punctuation(),
+        "space" => chr.is_whitespace(),
+        "upper" => chr.is_uppercase(),
+        "xdigit" => chr.is_ascii_hexdigit(),
+        _ => false,
+    }
+}
+
+/// This function escapes the characters that have a meaning in a pattern so that the text matches literally.
+/// It is used for the parts of a pattern that were quoted.
+pub fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for chr in text.chars() {
+        if matches!(chr, '*' | '?' | '[' | ']' | '\\') {
+            escaped.push('\\');
+        }
+        escaped.push(chr);
+    }
+    escaped
+}
+
+/// This function checks if a pattern has a `*`, `?` or `[` that is not escaped.
+pub fn has_wildcard(pattern: &str) -> bool {
+    let mut chars = pattern.chars();
+    while let Some(chr) = chars.next() {
+        match chr {
+            '\\' => {
+                chars.next();
+            },
+            '*' | '?' | '[' => return true,
+            _ => {},
+        }
+    }
+    false
+}
+
+/// This function removes the backslashes that escape characters in a pattern.
+pub fn unescape(pattern: &str) -> String {
+    let mut unescaped = String::with_capacity(pattern.len());
+    let mut chars = pattern.chars();
+    while let Some(chr) = chars.next() {
+        match chr {
+            '\\' => unescaped.extend(chars.next()),
+            chr => unescaped.push(chr),
+        }
+    }
+    unescaped
+}
+
+/// This function finds the paths that match a pattern and returns them sorted.
+/// The pattern is matched one component between slashes at a time against the entries of the directories
+/// that matched so far, and a slash is never matched by a wildcard.
+/// A name that starts with a dot has to be matched by a literal dot unless dotglob is set,
+/// and `.` and `..` are never matched by a wildcard.
+pub fn expand_path(pattern: &str, dotglob: bool) -> Vec<String> {
+    let (mut paths, rest) = match pattern.strip_prefix('/') {
+        Some(rest) => (vec!["/".to_string()], rest),
+        None => (vec![String::new()], pattern),
+    };
+
+    for component in rest.split('/') {
+        let mut matched = Vec::new();
+        for path in paths {
+            if component.is_empty() {
+                // a trailing or doubled slash only matches directories
+                if path.is_empty() || Path::new(&path).is_dir() {
+                    matched.push(format!("{}/", path.trim_end_matches('/')));
+                }
+            }
+            else if !has_wildcard(component) {
+                let joined = join_path(&path, &unescape(component));
+                if Path::new(&joined).symlink_metadata().is_ok() {
+                    matched.push(joined);
+                }
+            }
+            else {
+                let directory = if path.is_empty() { "." } else { path.as_str() };
+                let entries = match fs::read_dir(directory) {
+                    Ok(entries) => entries,
+                    Err(_) => continue,
+                };
+                let match_hidden = dotglob || component.starts_with('.') || component.starts_with("\\.");
+                for entry in entries.flatten() {
+                    let name = match entry.file_name().into_string() {
+                        Ok(name) => name,
+                        Err(_) => continue,
+                    };
+                    if name.starts_with('.') && !match_hidden {
+                        continue;
+                    }
+                    if pattern_match(component, &name) {
+                        matched.push(join_path(&path, &name));
+                    }
+                }
+            }
+        }
+        paths = matched;
+    }
+
+    paths.sort();
+    paths
+}
+
+fn join_path(path: &str, name: &str) -> String {
+    if path.is_empty() || path.ends_with('/') {
+        format!("{}{}", path, name)
+    }
+    else {
+        format!("{}/{}", path, name)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_pattern_match() {
+        assert!(pattern_match("*", ""));
+        assert!(pattern_match("*.rs", "main.rs"));
+        assert!(!pattern_match("*.rs", "main.rsx"));
+        assert!(pattern_match("a*b*c", "aXXbYYc"));
+        assert!(pattern_match("?at", "cat"));
+        assert!(!pattern_match("?at", "at"));
+        assert!(pattern_match("[ch]at", "hat"));
+        assert!(!pattern_match("[!ch]at", "hat"));
+        assert!(pattern_match("[a-c]x", "bx"));
+        assert!(pattern_match("[]]", "]"));
+        assert!(pattern_match("[[:digit:]]*", "9lives"));
+        assert!(pattern_match("[abc", "[abc"));
+        assert!(pattern_match("\\*", "*"));
+        assert!(!pattern_match("\\*", "x"));
+    }
+
+    #[test]
+    fn test_wildcards() {
+        assert!(has_wildcard("src/*.rs"));
+        assert!(!has_wildcard("src/\\*.rs"));
+        assert_eq!(unescape("a\\*b\\\\"), "a*b\\");
+    }
+
+    #[test]
+    fn test_escape() {
+        assert!(pattern_match(&escape("a*[b]?"), "a*[b]?"));
+        assert!(!pattern_match(&escape("a*"), "abc"));
+    }
+}
@@ -452,6 +452,13 @@ pub fn wait_for_job(job: Option<Rc<RefCell<Job>>>) -> WaitStatus {
 
     let job = job.unwrap();
 
+    // do_wait returns once any process has changed state, so a pipeline needs it to be called until every process has
+    while job.borrow().state == JobState::Running {
+        if do_wait(DOWAIT_BLOCK, &Some(job.clone())) != 0 {
+            break;
+        }
+    }
+
     status = job.borrow().stop_status;
 
     if job.borrow().state == JobState::Finished || matches!(job.borrow().stop_status,WaitStatus::Exited(_, _)) {
@@ -534,8 +541,11 @@ fn wait_one(block: usize, job: &Option<Rc<RefCell<Job>>>) -> Result<Option<Pid>,
                 stopped = true;
             }
         }
-        let stop_status = jb.borrow().processes[0].status.unwrap();
-        jb.borrow_mut().stop_status = stop_status;
+        // the processes of a pipeline can finish in any order
+        let stop_status = jb.borrow().processes[0].status;
+        if let Some(stop_status) = stop_status {
+            jb.borrow_mut().stop_status = stop_status;
+        }
         if this_job.is_some() {
             if state != JobState::Running {
                 this_job.as_ref().unwrap().borrow_mut().changed = true;
@@ -1,6 +1,8 @@
 use std::os::unix::io::RawFd;
 use std::str::{self, CharIndices};
 use std::fmt::{self, Display, Formatter};
+use crate::ast::{Assignment, AssignmentValue, ConditionalExpression, Parameter, ParameterOperation, Subscript, Word, WordSegment};
+use crate::condition;
 
 /// A result type wrapping a token with start and end locations.
 pub type Span<T, E> = Result<(usize, T, usize), E>;
@@ -9,6 +11,24 @@ pub type Span<T, E> = Result<(usize, T, usize), E>;
 #[derive(Debug)]
 pub enum Error {
     UnrecognizedChar(usize, char, usize),
+    /// The input ended inside a quote or an expansion that starts at the location
+    /// and needs the character to close it.
+    Unterminated(usize, char),
+    /// The expression of a `[[ ]]` command has a word that does not belong where it is,
+    /// with the location of that word and a message that says what is wrong.
+    Conditional(usize, usize, String),
+}
+
+impl Error {
+    /// This function moves the location of an error that was found in a part of the input
+    /// that starts at `offset`.
+    fn offset(self, offset: usize) -> Error {
+        match self {
+            Error::UnrecognizedChar(start, chr, end) => Error::UnrecognizedChar(start + offset, chr, end + offset),
+            Error::Unterminated(start, close) => Error::Unterminated(start + offset, close),
+            Error::Conditional(start, end, message) => Error::Conditional(start + offset, end + offset, message),
+        }
+    }
 }
 
 impl Display for Error {
@@ -16,7 +36,11 @@ impl Display for Error {
         match self {
             Error::UnrecognizedChar(start, chr, end) => {
                 write!(f, "Unrecognized character '{}' at position {}", chr, start)
-            }
+            },
+            Error::Unterminated(_, close) => {
+                write!(f, "unexpected end of input while looking for matching `{}'", close)
+            },
+            Error::Conditional(_, _, message) => write!(f, "{}", message),
         }
     }
 }
@@ -29,6 +53,7 @@ pub enum Token<'input> {
     NewlineList,
     Comment,
     SemiColon,
+    DoubleSemiColon,
     Pipe,
     BackTick,
     Dollar,
@@ -40,8 +65,10 @@ pub enum Token<'input> {
     Less,
     DoubleGreater,
     DoubleLess,
+    DoubleLessDash,
     GreaterAnd,
     LessAnd,
+    LessGreater,
     Ampersand,
     Equals,
     And,
@@ -65,6 +92,10 @@ pub enum Token<'input> {
     Return,*/
     EOF,
     Subshell(&'input str),
+    Arithmetic(&'input str),
+    Conditional(&'input str),
+    Assignment(&'input str),
+    HereDoc(&'input str),
     Number(RawFd),
     Word(&'input str),
 }
@@ -78,6 +109,7 @@ impl Display for Token<'_> {
             Token::NewlineList => write!(f, "NewlineList"),
             Token::Comment => write!(f, "Comment"),
             Token::SemiColon => write!(f, "SemiColon"),
+            Token::DoubleSemiColon => write!(f, "DoubleSemiColon"),
             Token::Pipe => write!(f, "Pipe"),
             Token::BackTick => write!(f, "BackTick"),
             Token::Dollar => write!(f, "Dollar"),
@@ -89,8 +121,10 @@ impl Display for Token<'_> {
             Token::Less => write!(f, "Less"),
             Token::DoubleGreater => write!(f, "DoubleGreater"),
             Token::DoubleLess => write!(f, "DoubleLess"),
+            Token::DoubleLessDash => write!(f, "DoubleLessDash"),
             Token::GreaterAnd => write!(f, "GreaterAnd"),
             Token::LessAnd => write!(f, "LessAnd"),
+            Token::LessGreater => write!(f, "LessGreater"),
             Token::Ampersand => write!(f, "Ampersand"),
             Token::Equals => write!(f, "Equals"),
             Token::And => write!(f, "And"),
@@ -114,6 +148,10 @@ impl Display for Token<'_> {
             Token::Return => write!(f, "Return"),*/
             Token::EOF => write!(f, "EOF"),
             Token::Subshell(s) => write!(f, "Subshell({})", s),
+            Token::Arithmetic(s) => write!(f, "Arithmetic({})", s),
+            Token::Conditional(s) => write!(f, "Conditional({})", s),
+            Token::HereDoc(s) => write!(f, "HereDoc({})", s),
+            Token::Assignment(s) => write!(f, "Assignment({})", s),
             Token::Number(n) => write!(f, "Number({})", n),
             Token::Word(s) => write!(f, "Word({})", s),
         }
@@ -121,8 +159,41 @@ impl Display for Token<'_> {
 }
 
 
+/// This enum tracks the words that follow `for` and `case`, where `in`, `do` and `esac` are reserved words
+/// even though they are not at the start of a command.
+#[derive(Debug,Clone,Copy,PartialEq)]
+enum Header {
+    None,
+    /// The next word is the name of the loop variable.
+    ForName,
+    /// The next word may be `in` or `do`.
+    ForIn,
+    /// The next word is the word that the case matches against.
+    CaseWord,
+    /// The next word may be `in`.
+    CaseIn,
+    /// The next word is a case pattern or `esac`.
+    Pattern,
+}
+
 pub struct Lexer<'input> {
     send_eof: bool,
+    /// Reserved words are only recognized when a new command may start here.
+    command_start: bool,
+    header: Header,
+    /// Set after `<<` or `<<-` so the next word is read as the delimiter of a here-document.
+    /// The flag tells if leading tabs are stripped from the body.
+    here_delimiter: Option<bool>,
+    /// The body of a here-document that is returned as the token after its delimiter.
+    here_body: Option<(usize, Token<'input>, usize)>,
+    /// Where lexing resumes once the end of the line with the here-document operators is reached,
+    /// which is just past the line with the delimiter of the last here-document.
+    here_resume: Option<usize>,
+    /// Set if the input ends where more of it is expected even though what is there can be parsed,
+    /// which is after a backslash or in a here-document that has not reached its delimiter.
+    incomplete: bool,
+    /// The comments that were skipped so far with where they start, which the formatter puts back.
+    comments: Vec<(usize, &'input str)>,
     input: &'input str,
     chars: CharIndices<'input>,
     lookahead: Option<(usize, char, usize)>,
@@ -136,6 +207,13 @@ impl<'input> Lexer<'input> {
         let lookahead = next.map(|n| (n.0, n.1, n.0 + n.1.len_utf8()));
         Lexer {
             send_eof: false,
+            command_start: true,
+            header: Header::None,
+            here_delimiter: None,
+            here_body: None,
+            here_resume: None,
+            incomplete: false,
+            comments: Vec::new(),
             input,
             chars,
             lookahead,
@@ -148,11 +226,31 @@ impl<'input> Iterator for Lexer<'input> {
 
     fn next(&mut self) -> Option<Self::Item> {
 
+        if let Some(body) = self.here_body.take() {
+            return Some(Ok(body));
+        }
+
         while let Some((start, chr, end)) = self.advance() {
             //eprintln!("{}: {}", start, chr);
             let token = match chr {
-                '\n' => Some(self.newline_list(start, end)),
-                ';' => Some(Ok((start, Token::SemiColon, end))),
+                '\n' => {
+                    // the lines after this one up to the last delimiter belong to here-documents
+                    if let Some(resume) = self.here_resume.take() {
+                        while matches!(self.lookahead, Some((pos, _, _)) if pos < resume) {
+                            self.advance();
+                        }
+                    }
+                    Some(self.newline_list(start, end))
+                },
+                ';' => {
+                    match self.lookahead {
+                        Some((_, ';', _)) => {
+                            self.advance();
+                            Some(Ok((start, Token::DoubleSemiColon, end)))
+                        },
+                        _ => Some(Ok((start, Token::SemiColon, end))),
+                    }
+                },
                 '|' => {
                     match self.lookahead {
                         Some((_, '|', _)) => {
@@ -171,34 +269,22 @@ impl<'input> Iterator for Lexer<'input> {
                         _ => Some(Ok((start, Token::Ampersand, end))),
                     }
                 },
-                '`' => Some(self.subshell(chr,start, end)),
-                '$' => {
-                    match self.lookahead {
-                        Some((_, '(', _)) => {
-                            Some(self.subshell(chr,start, end))
-                        }
-
-                        Some((_, '{', _)) => {
-
-                            Some(Ok((start, Token::Dollar, end)))
-                        }
-                         _ => Some(self.word(start,end)),
-                        
-                    }
-                },
                 '#' => {
-                    while let Some((_, chr, _)) = self.lookahead {
-                        match chr {
-                            '\n' => break,
-                            _ => self.advance(),
-                        };
-                    }
+                    self.comment(start);
                     self.next()
                 },
+                '(' if self.command_start && matches!(self.lookahead, Some((_, '(', _))) => {
+                    match self.arithmetic(start) {
+                        Some(token) => Some(Ok(token)),
+                        None => Some(Ok((start, Token::OpenParen, end))),
+                    }
+                },
                 '(' => Some(Ok((start, Token::OpenParen, end))),
                 ')' => Some(Ok((start, Token::CloseParen, end))),
-                '{' => Some(Ok((start, Token::OpenBrace, end))),
-                '}' => Some(Ok((start, Token::CloseBrace, end))),
+                // braces are only reserved words when they stand alone, otherwise they are part of a word like `{a,b}`
+                '{' if self.ends_word() => Some(Ok((start, Token::OpenBrace, end))),
+                '}' if self.ends_word() => Some(Ok((start, Token::CloseBrace, end))),
+                '<' | '>' if matches!(self.lookahead, Some((_, '(', _))) => Some(self.word(start)),
                 '>' => {
                     match self.lookahead {
                         Some((_, '>', _)) => {
@@ -216,29 +302,64 @@ impl<'input> Iterator for Lexer<'input> {
                     match self.lookahead {
                         Some((_, '<', _)) => {
                             self.advance();
-                            Some(Ok((start, Token::DoubleLess, end)))
+                            if let Some((_, '-', end)) = self.lookahead {
+                                self.advance();
+                                self.here_delimiter = Some(true);
+                                Some(Ok((start, Token::DoubleLessDash, end)))
+                            }
+                            else {
+                                self.here_delimiter = Some(false);
+                                Some(Ok((start, Token::DoubleLess, end)))
+                            }
                         },
                         Some((_, '&', _)) => {
                             self.advance();
                             Some(Ok((start, Token::LessAnd, end)))
                         },
+                        Some((_, '>', _)) => {
+                            self.advance();
+                            Some(Ok((start, Token::LessGreater, end)))
+                        },
                         _ => Some(Ok((start, Token::Less, end))),
                     }
                 },
-                '=' => Some(Ok((start, Token::Equals, end))),
-                '"' => Some(self.double_quote(start, end)),
-                '\'' => Some(self.single_quote(start, end)),
-                '!' => Some(Ok((start, Token::Bang, end))),
-                chr if is_word_start(chr) => Some(self.word(start, end)),
+                '!' if self.command_start => Some(Ok((start, Token::Bang, end))),
+                // a backslash before a newline between words joins the lines without making a word,
+                // and at the end of the input it joins them with the line that comes next
+                '\\' if matches!(self.lookahead, Some((_, '\n', _))) => {
+                    self.advance();
+                    self.incomplete |= self.lookahead.is_none();
+                    continue;
+                },
+                chr if is_word_start(chr) => Some(self.word(start)),
                 chr if chr.is_whitespace() => continue,
                 chr => Some(Err(Error::UnrecognizedChar(start, chr, end))),
             };
+            if let Some(Ok((_, tok, _))) = &token {
+                if let (Token::Word(delimiter), Some(strip_tabs)) = (tok, self.here_delimiter) {
+                    self.here_delimiter = None;
+                    self.here_body = Some(self.here_document(delimiter, strip_tabs));
+                }
+                self.command_start = starts_command(tok);
+                self.header = match (tok, self.header) {
+                    (Token::For, _) => Header::ForName,
+                    (Token::Word(_), Header::ForName) => Header::ForIn,
+                    (Token::NewlineList, Header::ForIn) => Header::ForIn,
+                    (Token::Case, _) => Header::CaseWord,
+                    (Token::Word(_), Header::CaseWord) => Header::CaseIn,
+                    (Token::NewlineList, Header::CaseIn) => Header::CaseIn,
+                    (Token::In, Header::CaseIn) => Header::Pattern,
+                    (Token::DoubleSemiColon, _) => Header::Pattern,
+                    (Token::NewlineList | Token::OpenParen | Token::Pipe, Header::Pattern) => Header::Pattern,
+                    _ => Header::None,
+                };
+            }
             return token;
         }
 
         if !self.send_eof {
             self.send_eof = true;
-            return Some(Ok((0, Token::EOF, 0)));
+            return Some(Ok((self.input.len(), Token::EOF, self.input.len())));
         }
         
 
@@ -249,6 +370,30 @@ impl<'input> Iterator for Lexer<'input> {
 }
 
 impl<'input> Lexer<'input> {
+    /// This function tells if the input that was lexed so far ends where more input is expected,
+    /// as it does after a backslash at the end or before the delimiter of a here-document.
+    pub fn is_incomplete(&self) -> bool {
+        self.incomplete
+    }
+
+    /// This function gives the comments that the lexer skipped so far with where they start.
+    pub fn comments(&self) -> &[(usize, &'input str)] {
+        &self.comments
+    }
+
+    /// Skips the rest of a comment that starts at `start` up to the end of its line and remembers it.
+    fn comment(&mut self, start: usize) {
+        let mut end = start + 1;
+        while let Some((_, chr, next)) = self.lookahead {
+            if chr == '\n' {
+                break;
+            }
+            end = next;
+            self.advance();
+        }
+        self.comments.push((start, &self.input[start..end]));
+    }
+
     fn advance(&mut self) -> Option<(usize, char, usize)> {
         match self.lookahead {
             Some((start, chr, end)) => {
@@ -260,110 +405,164 @@ impl<'input> Lexer<'input> {
         }
     }
 
-    fn take_until<F>(&mut self, start: usize, mut end: usize,  mut terminate: F)
-        -> (&'input str, usize)
-        where F: FnMut(char) -> bool
-    {
-        while let Some((_, c, _)) = self.lookahead {
-            if terminate(c) {
-                return (&self.input[start..end], end);
-            } else if let Some((_, _, e)) = self.advance() {
-                end = e;
-            }
-        } 
-        (&self.input[start..end], end)
-    }
-    
-    fn take_until_alt<F>(&mut self, start: usize, mut end: usize,  mut terminate: F)
-        -> (&'input str, usize)
-        where F: FnMut(char) -> bool
-    {
-        while let Some((_, c, _)) = self.lookahead {
-            if terminate(c) {
-                let e = self.advance();
-                end = e.unwrap().2;
-                return (&self.input[start..end], end);
-            } else if let Some((_, _, e)) = self.advance() {
-                end = e;
-            }
-        } 
-        (&self.input[start..end], end)
-    }
-    fn take_until_seen_twice<F>(&mut self, start: usize, mut end: usize,  mut terminate: F)
-        -> (&'input str, usize)
-        where F: FnMut(char) -> bool
-    {
-        //eprintln!("{:?}",self.input);
-        let mut count = 0;
-        while let Some((_, c, _)) = self.lookahead {
-            if count == 2 {
-                return (&self.input[start..end], end);
+    /// Finds the body of a here-document, which starts on the line after the current one
+    /// or after the body of the previous here-document on the same line.
+    /// The body ends before the first line that is equal to the delimiter with its quotes removed.
+    /// With `<<-` leading tabs are ignored when looking for the delimiter line.
+    fn here_document(&mut self, delimiter: &str, strip_tabs: bool) -> (usize, Token<'input>, usize) {
+        let delimiter = unquote_delimiter(delimiter);
+        let start = match self.here_resume {
+            Some(resume) => resume,
+            None => {
+                let position = self.lookahead.map_or(self.input.len(), |(pos, _, _)| pos);
+                match self.input[position..].find('\n') {
+                    Some(newline) => position + newline + 1,
+                    None => self.input.len(),
+                }
+            },
+        };
+
+        let mut line_start = start;
+        while line_start < self.input.len() {
+            let line_end = match self.input[line_start..].find('\n') {
+                Some(newline) => line_start + newline,
+                None => self.input.len(),
+            };
+            let mut line = &self.input[line_start..line_end];
+            if strip_tabs {
+                line = line.trim_start_matches('\t');
             }
-            else if terminate(c) {
-                count += 1;
-            } 
-            else if let Some((_, _, e)) = self.advance() {
-                end = e;
+            if line == delimiter {
+                self.here_resume = Some((line_end + 1).min(self.input.len()));
+                return (start, Token::HereDoc(&self.input[start..line_start]), line_start);
             }
-        } 
-        (&self.input[start..end], end)
-    }
+            line_start = line_end + 1;
+        }
 
-    fn take_while<F>(&mut self, start: usize, end: usize, mut keep_going: F)
-        -> (&'input str, usize)
-        where F: FnMut(char) -> bool,
-    {
-        self.take_until(start, end, |c| !keep_going(c))
+        // a missing delimiter ends the here-document at the end of the input
+        self.incomplete = true;
+        self.here_resume = Some(self.input.len());
+        (start, Token::HereDoc(&self.input[start..]), self.input.len())
     }
 
-    fn single_quote(&mut self, start: usize, end: usize) -> Result<(usize, Token<'input>, usize), Error> {
-        let (word, end) = self.take_until_alt(start, end, |c| c == '\'');
-        //self.advance();
-        Ok((start, Token::Word(&word[0..]), end))
-    }
-    fn double_quote(&mut self, start: usize, end: usize) -> Result<(usize, Token<'input>, usize), Error> {
-        let (word, end) = self.take_until_alt(start, end, |c| c == '"');
-        //self.advance();
-        Ok((start, Token::Word(&word[0..]), end))
+    /// Checks if the input ends or a word cannot continue at the next character.
+    fn ends_word(&self) -> bool {
+        match self.lookahead {
+            Some((_, chr, _)) => !is_word_continue(chr),
+            None => true,
+        }
     }
 
-    fn subshell(&mut self, chr: char, start: usize, end: usize) -> Result<(usize, Token<'input>, usize), Error> {
-
-        match chr {
-            '$' => {
-                let (word, end) = self.take_until_alt(start, end, |c| c == ')');
-
-                return Ok((start, Token::Word(&word[0..]), end));
-            },
-            '`' => {
-                let (word, end) = self.take_until_alt(start, end, |c| c == '`');
-                return Ok((start, Token::Word(&word[0..]), end));
-            },
-            _ => {
-                unreachable!();
+    /// Reads a `(( ))` command that starts at `start`.
+    /// None is returned if the parentheses are not closed by `))`, as in `((cmd) | cmd)`,
+    /// in which case the first `(` starts a subshell.
+    fn arithmetic(&mut self, start: usize) -> Option<(usize, Token<'input>, usize)> {
+        let bytes = self.input.as_bytes();
+        let mut depth = 0;
+        let mut pos = start + 2;
+        while pos < bytes.len() {
+            match bytes[pos] {
+                b'(' => depth += 1,
+                b')' if depth > 0 => depth -= 1,
+                b')' => {
+                    if bytes.get(pos + 1) != Some(&b')') {
+                        return None;
+                    }
+                    let end = pos + 2;
+                    while matches!(self.lookahead, Some((pos, _, _)) if pos < end) {
+                        self.advance();
+                    }
+                    return Some((start, Token::Arithmetic(&self.input[start + 2..pos]), end));
+                },
+                _ => {},
             }
+            pos += 1;
         }
+        None
     }
 
+    /// Reads a `[[ ]]` command that starts at `start`, up to the `]]` that closes it.
+    /// The token holds the whole command, brackets and all.
+    fn conditional(&mut self, start: usize) -> Result<(usize, Token<'input>, usize), Error> {
+        let tokens = conditional_tokens(&self.input[start..]).map_err(|err| err.offset(start))?;
+        let end = tokens.last().map_or(start, |(pos, token)| start + pos + token.len());
+        while matches!(self.lookahead, Some((pos, _, _)) if pos < end) {
+            self.advance();
+        }
+        Ok((start, Token::Conditional(&self.input[start..end]), end))
+    }
+
+    /// Collapses a run of newlines into a single NewlineList token.
+    /// Blank lines, indentation and comment lines between the newlines are swallowed as well
+    /// so that the grammar only ever sees one separator.
     fn newline_list(&mut self, start: usize, end: usize) -> Result<(usize, Token<'input>, usize), Error> {
+        let mut end = end;
         while let Some((_, chr, _)) = self.lookahead {
             //eprintln!("chr: {}", chr);
             match chr {
-                '\n' => self.advance(),
+                '\n' => {
+                    end = self.advance().unwrap().2;
+                },
+                '#' => {
+                    let (start, _, _) = self.advance().unwrap();
+                    self.comment(start);
+                },
+                chr if chr.is_whitespace() => {
+                    self.advance();
+                },
                 _ => break,
             };
         }
         Ok((start, Token::NewlineList, end))
     }
 
-    fn word(&mut self, start: usize, end: usize) -> Result<(usize, Token<'input>, usize), Error> {
+    /// Reads a whole word. A word runs until whitespace or an operator character that is not quoted.
+    /// Quotes, backslash escapes, backquotes, `${...}` and `$(...)` stay part of the word
+    /// even when they hold characters that would end it otherwise.
+    fn word(&mut self, start: usize) -> Result<(usize, Token<'input>, usize), Error> {
 
-        let (word, end) = self.take_while(start, end, is_word_continue);
+        let mut parser = WordParser::new(&self.input[start..]);
+        parser.segments(Context::Word);
+        if let Some(close) = parser.unterminated {
+            return Err(Error::Unterminated(start, close));
+        }
+        self.incomplete |= parser.trailing_backslash;
+        let mut end = start + parser.pos;
+        // the list of an array assignment like `name=(a b c)` belongs to the assignment
+        if self.command_start && self.input[end..].starts_with('(') && matches!(split_assignment(&self.input[start..end]), Some((_, None, _, ""))) {
+            let mut list = WordParser::new(&self.input[end..]);
+            list.parenthesized();
+            if let Some(close) = list.unterminated {
+                return Err(Error::Unterminated(start, close));
+            }
+            end += list.pos;
+        }
+        while matches!(self.lookahead, Some((pos, _, _)) if pos < end) {
+            self.advance();
+        }
+        let word = &self.input[start..end];
         //eprintln!("word: {}", word);
+        match (self.header, word) {
+            (Header::ForIn | Header::CaseIn, "in") => return Ok((start, Token::In, end)),
+            (Header::ForIn, "do") => return Ok((start, Token::Do, end)),
+            (Header::Pattern, "esac") => return Ok((start, Token::Esac, end)),
+            (Header::Pattern, _) => return Ok((start, Token::Word(word), end)),
+            _ => {},
+        }
+        if !self.command_start {
+            return Ok((start, self.num_or_word(word), end));
+        }
+        if is_assignment(word) {
+            return Ok((start, Token::Assignment(word), end));
+        }
+        if word == "[[" {
+            return self.conditional(start);
+        }
         let token = match word {
             "for" => Token::For,
             "in" => Token::In,
             "while" => Token::While,
+            "until" => Token::Until,
             "if" => Token::If,
             "then" => Token::Then,
             "else" => Token::Else,
@@ -394,6 +593,659 @@ impl<'input> Lexer<'input> {
 }
 
 
+/// This function decides if the token following `token` is in a position where a command can start,
+/// which is the only place where reserved words such as `if` or `done` are recognized.
+fn starts_command(token: &Token) -> bool {
+    matches!(token,
+        Token::NewlineList | Token::SemiColon | Token::Ampersand | Token::And | Token::Or | Token::Assignment(_)
+            | Token::Pipe | Token::Bang | Token::OpenParen | Token::CloseParen | Token::OpenBrace
+            | Token::If | Token::Then | Token::Else | Token::Elif | Token::While | Token::Until
+            | Token::Do)
+}
+
+/// This function checks if a word is an assignment of the form `name=value`, `name[index]=value` or `name+=value`.
+/// The name can be made of namespaces joined by `::`.
+fn is_assignment(word: &str) -> bool {
+    split_assignment(word).is_some()
+}
+
+/// This function splits an assignment into its name, the text of its index if it has one,
+/// whether it appends with `+=` and the text of its value.
+pub fn split_assignment(word: &str) -> Option<(&str, Option<&str>, bool, &str)> {
+    let (name, mut rest) = word.split_at(word.find(['[', '+', '='])?);
+    if !name.split("::").all(is_name) {
+        return None;
+    }
+    let mut index = None;
+    if let Some(after) = rest.strip_prefix('[') {
+        let mut depth = 0;
+        let close = after.find(|chr| {
+            match chr {
+                '[' => depth += 1,
+                ']' if depth == 0 => return true,
+                ']' => depth -= 1,
+                _ => {},
+            }
+            false
+        })?;
+        index = Some(&after[..close]);
+        rest = &after[close + 1..];
+    }
+    match rest.strip_prefix("+=") {
+        Some(value) => Some((name, index, true, value)),
+        None => Some((name, index, false, rest.strip_prefix('=')?)),
+    }
+}
+
+/// This function parses a word that the lexer found to be an assignment.
+/// The list in parentheses of `name=(...)` is split into the words that become the elements of the array.
+pub fn parse_assignment(word: &str) -> Assignment {
+    let (name, index, append, value) = split_assignment(word).unwrap_or((word, None, false, ""));
+    let index = index.map(|index| Word(WordParser::new(index).segments(Context::Subscript)));
+    let value = match value.strip_prefix('(').and_then(|list| list.strip_suffix(')')) {
+        Some(list) if index.is_none() => AssignmentValue::Array(split_words(list)),
+        _ => AssignmentValue::Scalar(parse_word(value)),
+    };
+    Assignment { name: name.to_string(), index, append, value }
+}
+
+/// This function splits the list of an array assignment into words at the whitespace between them.
+/// The list can span lines and have comments in it.
+fn split_words(list: &str) -> Vec<Word> {
+    let mut words = Vec::new();
+    let mut rest = list.trim_start();
+    while let Some(chr) = rest.chars().next() {
+        if chr == '#' {
+            rest = rest.find('\n').map_or("", |end| rest[end..].trim_start());
+            continue;
+        }
+        if let Some(after) = rest.strip_prefix("\\\n") {
+            rest = after.trim_start();
+            continue;
+        }
+        let mut parser = WordParser::new(rest);
+        let segments = parser.segments(Context::Word);
+        // an operator cannot start a word, so it is taken as it is
+        if parser.pos == 0 {
+            parser.pos = chr.len_utf8();
+            words.push(Word(vec![WordSegment::Literal(chr.to_string())]));
+        }
+        else {
+            words.push(Word(segments));
+        }
+        rest = rest[parser.pos..].trim_start();
+    }
+    words
+}
+
+/// This function checks if a string is a valid variable name.
+pub fn is_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(chr) if chr.is_ascii_alphabetic() || chr == '_' => chars.all(|chr| chr.is_ascii_alphanumeric() || chr == '_'),
+        _ => false,
+    }
+}
+
+/// This function parses a word of a command into the segments that are expanded differently.
+pub fn parse_word(word: &str) -> Word {
+    let mut parser = WordParser::new(word);
+    Word(parser.segments(Context::Word))
+}
+
+/// This function parses the expression of a `(( ))` command the same way as the inside of `$(( ))`.
+pub fn parse_arithmetic(expression: &str) -> Word {
+    let mut parser = WordParser::new(expression);
+    Word(parser.segments(Context::Parenthesized))
+}
+
+/// This function parses the body of a here-document, which is expanded like the inside of double quotes.
+pub fn parse_here_document(body: &str) -> Vec<WordSegment> {
+    let mut parser = WordParser::new(body);
+    parser.segments(Context::HereDocument)
+}
+
+/// This function parses the expression of a `[[ ]]` command, which is the whole command with its brackets
+/// that starts at `offset` in the input. `!` binds more tightly than `&&`, which binds more tightly than `||`.
+pub fn parse_conditional(command: &str, offset: usize) -> Result<ConditionalExpression, Error> {
+    let tokens = conditional_tokens(command).map_err(|err| err.offset(offset))?;
+    let mut parser = ConditionalParser { tokens, pos: 0 };
+    let expression = parser.or().and_then(|expression| match parser.peek() {
+        "]]" => Ok(expression),
+        _ => Err(parser.unexpected()),
+    });
+    expression.map_err(|err| err.offset(offset))
+}
+
+/// This function splits a `[[ ]]` command into the words and operators of its expression with their locations,
+/// the last of them being the `]]` that ends it. Newlines are blanks inside the brackets, and `&&`, `||`,
+/// parentheses, `<` and `>` are operators even without blanks around them.
+/// The word after `=~` is a regular expression, where parentheses and `|` are part of the word.
+fn conditional_tokens(command: &str) -> Result<Vec<(usize, &str)>, Error> {
+    let mut tokens = Vec::new();
+    let mut pos = 2;
+    loop {
+        let rest = &command[pos..];
+        let trimmed = rest.trim_start();
+        pos += rest.len() - trimmed.len();
+        if trimmed.is_empty() {
+            return Err(Error::Unterminated(0, ']'));
+        }
+        let regex = matches!(tokens.last(), Some((_, "=~")));
+        let length = if !regex && (trimmed.starts_with("&&") || trimmed.starts_with("||")) {
+            2
+        }
+        else if !regex && trimmed.starts_with(['(', ')', '<', '>']) {
+            1
+        }
+        else {
+            let mut parser = WordParser::new(trimmed);
+            parser.segments(if regex { Context::Regex } else { Context::Word });
+            if let Some(close) = parser.unterminated {
+                return Err(Error::Unterminated(pos, close));
+            }
+            parser.pos
+        };
+        if length == 0 {
+            let chr = trimmed.chars().next().unwrap();
+            return Err(Error::UnrecognizedChar(pos, chr, pos + chr.len_utf8()));
+        }
+        let token = &trimmed[..length];
+        tokens.push((pos, token));
+        if token == "]]" {
+            return Ok(tokens);
+        }
+        pos += length;
+    }
+}
+
+/// This struct parses the expression of a `[[ ]]` command from its words and operators.
+struct ConditionalParser<'input> {
+    tokens: Vec<(usize, &'input str)>,
+    pos: usize,
+}
+
+impl<'input> ConditionalParser<'input> {
+    /// The word or operator at the current position. The `]]` at the end is never consumed.
+    fn peek(&self) -> &'input str {
+        self.tokens[self.pos].1
+    }
+
+    fn unexpected(&self) -> Error {
+        let (start, token) = self.tokens[self.pos];
+        Error::Conditional(start, start + token.len(), format!("unexpected `{}' in conditional expression", token))
+    }
+
+    fn or(&mut self) -> Result<ConditionalExpression, Error> {
+        let mut expression = self.and()?;
+        while self.peek() == "||" {
+            self.pos += 1;
+            expression = ConditionalExpression::Or(Box::new(expression), Box::new(self.and()?));
+        }
+        Ok(expression)
+    }
+
+    fn and(&mut self) -> Result<ConditionalExpression, Error> {
+        let mut expression = self.not()?;
+        while self.peek() == "&&" {
+            self.pos += 1;
+            expression = ConditionalExpression::And(Box::new(expression), Box::new(self.not()?));
+        }
+        Ok(expression)
+    }
+
+    fn not(&mut self) -> Result<ConditionalExpression, Error> {
+        if self.peek() == "!" {
+            self.pos += 1;
+            return Ok(ConditionalExpression::Not(Box::new(self.not()?)));
+        }
+        self.primary()
+    }
+
+    /// Parses a parenthesized expression, a test with a unary operator, a comparison or a word on its own.
+    fn primary(&mut self) -> Result<ConditionalExpression, Error> {
+        if self.peek() == "(" {
+            self.pos += 1;
+            let expression = self.or()?;
+            if self.peek() != ")" {
+                return Err(self.unexpected());
+            }
+            self.pos += 1;
+            return Ok(expression);
+        }
+        let word = self.operand()?;
+        if condition::is_unary_operator(word) {
+            return Ok(ConditionalExpression::Unary(word.to_string(), parse_word(self.operand()?)));
+        }
+        let operator = self.peek();
+        if !condition::is_binary_operator(operator) && operator != "=~" {
+            return Ok(ConditionalExpression::Word(parse_word(word)));
+        }
+        self.pos += 1;
+        let rhs = self.operand()?;
+        let rhs = match operator {
+            "=~" => Word(WordParser::new(rhs).segments(Context::Regex)),
+            _ => parse_word(rhs),
+        };
+        Ok(ConditionalExpression::Binary(parse_word(word), operator.to_string(), rhs))
+    }
+
+    /// Takes a word that an operator applies to, which cannot be an operator of the expression or the `]]` at the end.
+    fn operand(&mut self) -> Result<&'input str, Error> {
+        match self.peek() {
+            "&&" | "||" | "(" | ")" | "<" | ">" | "]]" => Err(self.unexpected()),
+            word => {
+                self.pos += 1;
+                Ok(word)
+            },
+        }
+    }
+}
+
+/// The places that a word can be parsed in, which differ in what ends the word and which characters are special.
+#[derive(Debug,Clone,Copy,PartialEq)]
+enum Context {
+    /// An unquoted word, which ends at whitespace or an operator.
+    Word,
+    /// The inside of double quotes up to the closing quote.
+    DoubleQuote,
+    /// The body of a here-document, where a double quote is an ordinary character.
+    HereDocument,
+    /// The word after the operator of a `${...}` expansion up to the closing brace.
+    Parameter,
+    /// The inside of `$(...)` or `$((...))` up to the parenthesis that closes it.
+    Parenthesized,
+    /// The index of an array up to the bracket that closes it.
+    Subscript,
+    /// The regular expression after `=~` in a `[[ ]]` command, which ends at a blank or a parenthesis
+    /// that it did not open.
+    Regex,
+}
+
+/// This struct parses a word into segments.
+/// The lexer uses it to find where a word ends as well, so the two always agree on that.
+struct WordParser<'input> {
+    text: &'input str,
+    pos: usize,
+    /// The character that would have closed the first quote or expansion that the text ends inside of.
+    unterminated: Option<char>,
+    /// Set if the text ends with a backslash that has nothing to escape, or with a backslash before the last newline.
+    trailing_backslash: bool,
+}
+
+impl<'input> WordParser<'input> {
+    fn new(text: &'input str) -> Self {
+        WordParser { text, pos: 0, unterminated: None, trailing_backslash: false }
+    }
+
+    /// Consumes the character that closes a quote or an expansion and remembers if it is missing.
+    fn close(&mut self, close: char) {
+        if self.peek() == Some(close) {
+            self.bump();
+        }
+        else if self.unterminated.is_none() {
+            self.unterminated = Some(close);
+        }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.rest().chars().next()
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let chr = self.peek()?;
+        self.pos += chr.len_utf8();
+        Some(chr)
+    }
+
+    fn rest(&self) -> &'input str {
+        &self.text[self.pos..]
+    }
+
+    /// Parses segments up to the end of the context, which is left for the caller to consume.
+    fn segments(&mut self, context: Context) -> Vec<WordSegment> {
+        let quoted = matches!(context, Context::DoubleQuote | Context::HereDocument);
+        let mut segments = Vec::new();
+        // the open braces of a parameter word, the open parentheses of a parenthesized one
+        // or the open brackets of a subscript
+        let mut depth = 0;
+        while let Some(chr) = self.peek() {
+            match (context, chr) {
+                // a process substitution can start a word
+                (Context::Word, '<' | '>') if self.pos == 0 && self.text[1..].starts_with('(') => {},
+                (Context::Word, chr) if !is_word_continue(chr) => break,
+                (Context::DoubleQuote, '"') => break,
+                (Context::Regex, chr) if chr.is_whitespace() && depth == 0 => break,
+                (Context::Parameter, '}') | (Context::Parenthesized | Context::Regex, ')') | (Context::Subscript, ']') if depth == 0 => break,
+                (Context::Parameter, '{') | (Context::Parenthesized | Context::Regex, '(') | (Context::Subscript, '[') => depth += 1,
+                (Context::Parameter, '}') | (Context::Parenthesized | Context::Regex, ')') | (Context::Subscript, ']') => depth -= 1,
+                _ => {},
+            }
+            self.bump();
+            match chr {
+                '\\' => self.backslash(&mut segments, context),
+                '\'' if !quoted => segments.push(WordSegment::SingleQuoted(self.single_quote())),
+                '"' if !quoted => {
+                    let inner = self.segments(Context::DoubleQuote);
+                    self.close('"');
+                    segments.push(WordSegment::DoubleQuoted(inner));
+                },
+                '`' => segments.push(self.backquote(context)),
+                '<' | '>' if context == Context::Word => segments.push(WordSegment::ProcessSubstitution(chr == '<', self.parenthesized())),
+                '$' => {
+                    match self.dollar() {
+                        Some(segment) => segments.push(segment),
+                        None => push_literal(&mut segments, '$'),
+                    }
+                },
+                chr => push_literal(&mut segments, chr),
+            }
+        }
+        segments
+    }
+
+    /// Handles what follows a backslash. Outside of quotes it escapes any character,
+    /// while inside double quotes and here-documents it only escapes the characters that are special there.
+    /// A backslash before a newline joins the two lines everywhere.
+    fn backslash(&mut self, segments: &mut Vec<WordSegment>, context: Context) {
+        match self.peek() {
+            Some('\n') => {
+                self.bump();
+                self.trailing_backslash |= self.peek().is_none();
+            },
+            Some(chr) if matches!(context, Context::DoubleQuote | Context::HereDocument) => {
+                if matches!(chr, '$' | '`' | '\\') || (chr == '"' && context == Context::DoubleQuote) {
+                    self.bump();
+                    push_literal(segments, chr);
+                }
+                else {
+                    push_literal(segments, '\\');
+                }
+            },
+            Some(chr) => {
+                self.bump();
+                segments.push(WordSegment::Escaped(chr));
+            },
+            None => {
+                self.trailing_backslash = true;
+                push_literal(segments, '\\');
+            },
+        }
+    }
+
+    /// Reads the text up to the closing single quote, which is consumed.
+    fn single_quote(&mut self) -> String {
+        let rest = self.rest();
+        let end = rest.find('\'').unwrap_or(rest.len());
+        self.pos += end;
+        self.close('\'');
+        rest[..end].to_string()
+    }
+
+    /// Reads a command substitution in backquotes up to the closing backquote.
+    /// A backslash inside only escapes `$`, `` ` `` and `\` (and `"` inside double quotes)
+    /// and is removed from the command in front of them.
+    fn backquote(&mut self, context: Context) -> WordSegment {
+        let mut command = String::new();
+        while let Some(chr) = self.peek() {
+            if chr == '`' {
+                break;
+            }
+            self.bump();
+            match chr {
+                '\\' => {
+                    match self.peek() {
+                        Some(next) if matches!(next, '$' | '`' | '\\') || (next == '"' && context == Context::DoubleQuote) => {
+                            self.bump();
+                            command.push(next);
+                        },
+                        _ => command.push('\\'),
+                    }
+                },
+                chr => command.push(chr),
+            }
+        }
+        self.close('`');
+        WordSegment::CommandSubstitution(command)
+    }
+
+    /// Parses what follows a `$`.
+    /// None is returned if the `$` does not start an expansion, in which case it is an ordinary character.
+    fn dollar(&mut self) -> Option<WordSegment> {
+        let start = self.pos - 1;
+        match self.peek()? {
+            '{' => {
+                self.bump();
+                Some(self.braced_parameter(start))
+            },
+            '(' if self.rest().starts_with("((") => {
+                let unterminated = self.unterminated;
+                self.pos += 2;
+                let expression = self.segments(Context::Parenthesized);
+                if self.rest().starts_with("))") {
+                    self.pos += 2;
+                    return Some(WordSegment::Arithmetic(Word(expression)));
+                }
+                // not closed by `))`, so it is a command substitution that starts with a subshell
+                self.pos = start + 1;
+                self.unterminated = unterminated;
+                Some(self.command_substitution())
+            },
+            '(' => Some(self.command_substitution()),
+            chr if chr.is_ascii_digit() || matches!(chr, '?' | '#' | '@' | '*') => {
+                self.bump();
+                Some(WordSegment::Parameter(Parameter { name: chr.to_string(), subscript: None, operation: None }))
+            },
+            chr if chr.is_ascii_alphabetic() || chr == '_' => {
+                let (name, _) = split_name(self.rest());
+                self.pos += name.len();
+                Some(WordSegment::Parameter(Parameter { name: name.to_string(), subscript: None, operation: None }))
+            },
+            _ => None,
+        }
+    }
+
+    /// Reads a `$(...)` command substitution from its open parenthesis to the one that closes it.
+    fn command_substitution(&mut self) -> WordSegment {
+        WordSegment::CommandSubstitution(self.parenthesized())
+    }
+
+    /// Reads a command in parentheses and returns the text between them.
+    /// The command is lexed to find the parenthesis that closes it, so that the ones in quotes, comments,
+    /// case patterns and nested commands do not end it early.
+    fn parenthesized(&mut self) -> String {
+        self.bump();
+        let start = self.pos;
+        self.pos = match command_end(self.rest()) {
+            Some(end) => start + end,
+            None => self.text.len(),
+        };
+        let command = self.text[start..self.pos].to_string();
+        self.close(')');
+        command
+    }
+
+    /// Parses a `${...}` expansion that starts at `start`, from just after its open brace.
+    /// Anything that is not a valid expansion becomes a BadSubstitution, which is reported once it is expanded.
+    fn braced_parameter(&mut self, start: usize) -> WordSegment {
+        if let Some(parameter) = self.parameter_expression() {
+            if self.peek() == Some('}') {
+                self.bump();
+                return WordSegment::Parameter(parameter);
+            }
+        }
+        self.segments(Context::Parameter);
+        self.close('}');
+        WordSegment::BadSubstitution(self.text[start..self.pos].to_string())
+    }
+
+    /// Parses the inside of a `${...}` expansion up to the closing brace, which is left for the caller.
+    /// Besides a plain name it handles `${#name}` for the length of the value,
+    /// `:-`, `:=`, `:?` and `:+` (and their forms without the colon, which only check if the variable is unset)
+    /// and the removal of the shortest or longest matching prefix with `#` and `##` or suffix with `%` and `%%`.
+    /// The name can have the subscript of an array after it, and `${!name[@]}` gives the indices of the array.
+    fn parameter_expression(&mut self) -> Option<Parameter> {
+        for (prefix, operation) in [('#', ParameterOperation::Length), ('!', ParameterOperation::Keys)] {
+            if let Some(after) = self.rest().strip_prefix(prefix) {
+                let (name, _) = split_name(after);
+                if name.is_empty() {
+                    continue;
+                }
+                let (start, unterminated) = (self.pos, self.unterminated);
+                self.pos += 1 + name.len();
+                let subscript = self.subscript();
+                let all = matches!(subscript, Some(Subscript::All | Subscript::Joined));
+                if self.peek() == Some('}') && (operation == ParameterOperation::Length || all) {
+                    return Some(Parameter { name: name.to_string(), subscript, operation: Some(operation) });
+                }
+                self.pos = start;
+                self.unterminated = unterminated;
+            }
+        }
+
+        let (name, _) = split_name(self.rest());
+        if name.is_empty() {
+            return None;
+        }
+        self.pos += name.len();
+        let name = name.to_string();
+        let subscript = self.subscript();
+        let rest = self.rest();
+        let operator = match ["##", "%%", ":-", ":=", ":?", ":+", "#", "%", "-", "=", "?", "+"].into_iter().find(|operator| rest.starts_with(operator)) {
+            Some(operator) => operator,
+            None => return Some(Parameter { name, subscript, operation: None }),
+        };
+        self.pos += operator.len();
+
+        let word = Word(self.segments(Context::Parameter));
+        let colon = operator.starts_with(':');
+        let longest = operator.len() == 2;
+        let operation = match operator.trim_start_matches(':') {
+            "-" => ParameterOperation::Default(colon, word),
+            "=" => ParameterOperation::Assign(colon, word),
+            "?" => ParameterOperation::Error(colon, word),
+            "+" => ParameterOperation::Alternative(colon, word),
+            "#" | "##" => ParameterOperation::RemovePrefix(longest, word),
+            _ => ParameterOperation::RemoveSuffix(longest, word),
+        };
+        Some(Parameter { name, subscript, operation: Some(operation) })
+    }
+
+    /// Parses the subscript of an array that follows the name in a `${...}` expansion, if there is one.
+    fn subscript(&mut self) -> Option<Subscript> {
+        let subscript = match self.rest() {
+            rest if rest.starts_with("[@]") => Subscript::All,
+            rest if rest.starts_with("[*]") => Subscript::Joined,
+            rest if rest.starts_with('[') => {
+                self.bump();
+                let index = Word(self.segments(Context::Subscript));
+                self.close(']');
+                return Some(Subscript::Index(index));
+            },
+            _ => return None,
+        };
+        self.pos += 3;
+        Some(subscript)
+    }
+}
+
+/// The part of a case command that the lexer is in when it looks for the end of a command in parentheses.
+#[derive(Debug,Clone,Copy,PartialEq)]
+enum CasePart {
+    /// The word before `in`.
+    Word,
+    /// A pattern, which a `)` ends.
+    Pattern,
+    /// The commands of a pattern, which `;;` ends.
+    Commands,
+}
+
+/// This function finds the parenthesis that closes a command in parentheses, such as that of `$(...)`,
+/// in the text after the open parenthesis. None is returned if the command is not closed.
+pub fn command_end(text: &str) -> Option<usize> {
+    let mut depth = 0;
+    // the case commands that the parenthesis is inside of
+    let mut cases = Vec::new();
+    for token in Lexer::new(text) {
+        let (start, token, _) = token.ok()?;
+        match (token, cases.last()) {
+            (Token::EOF, _) => return None,
+            (Token::Case, _) => cases.push(CasePart::Word),
+            (Token::In, Some(CasePart::Word)) => *cases.last_mut()? = CasePart::Pattern,
+            // a pattern can start with an open parenthesis of its own
+            (Token::OpenParen, Some(CasePart::Pattern)) => {},
+            (Token::CloseParen, Some(CasePart::Pattern)) => *cases.last_mut()? = CasePart::Commands,
+            (Token::DoubleSemiColon, Some(CasePart::Commands)) => *cases.last_mut()? = CasePart::Pattern,
+            (Token::Esac, Some(_)) => {
+                cases.pop();
+            },
+            (Token::OpenParen, _) => depth += 1,
+            (Token::CloseParen, _) if depth == 0 => return Some(start),
+            (Token::CloseParen, _) => depth -= 1,
+            _ => {},
+        }
+    }
+    None
+}
+
+fn push_literal(segments: &mut Vec<WordSegment>, chr: char) {
+    match segments.last_mut() {
+        Some(WordSegment::Literal(text)) => text.push(chr),
+        _ => segments.push(WordSegment::Literal(chr.to_string())),
+    }
+}
+
+/// This function splits the name of a parameter off the front of the text after a `$` or `${`.
+/// The special parameters are a single character, positional parameters can have several digits
+/// and other names can be made of namespaces joined by `::`.
+fn split_name(text: &str) -> (&str, &str) {
+    let first = match text.chars().next() {
+        Some(chr) => chr,
+        None => return ("", ""),
+    };
+    let end = if matches!(first, '?' | '#' | '@' | '*') {
+        1
+    }
+    else if first.is_ascii_digit() {
+        text.find(|chr: char| !chr.is_ascii_digit()).unwrap_or(text.len())
+    }
+    else {
+        let mut end = 0;
+        let bytes = text.as_bytes();
+        while end < bytes.len() {
+            if bytes[end].is_ascii_alphanumeric() || bytes[end] == b'_' {
+                end += 1;
+            }
+            else if end > 0 && text[end..].starts_with("::") && matches!(bytes.get(end + 2), Some(byte) if byte.is_ascii_alphabetic() || *byte == b'_') {
+                end += 2;
+            }
+            else {
+                break;
+            }
+        }
+        end
+    };
+    text.split_at(end)
+}
+
+/// This function removes the quotes and backslashes from the delimiter of a here-document.
+pub fn unquote_delimiter(delimiter: &str) -> String {
+    let mut unquoted = String::new();
+    let mut chars = delimiter.chars();
+    while let Some(chr) = chars.next() {
+        match chr {
+            '\'' | '"' => {},
+            '\\' => {
+                if let Some(chr) = chars.next() {
+                    unquoted.push(chr);
+                }
+            },
+            chr => unquoted.push(chr),
+        }
+    }
+    unquoted
+}
+
 fn is_word_start(chr: char) -> bool {
     //eprintln!("chr is_word_start: {}", chr);
     let result = match chr {
@@ -406,9 +1258,40 @@ fn is_word_start(chr: char) -> bool {
 
 fn is_word_continue(chr: char) -> bool {
     match chr {
-        ';' | '&' | '|' | '(' | ')' | '{' | '}' | '<' | '>' | '!' | '$' | '`' | '*' | '=' => false,
+        ';' | '&' | '|' | '(' | ')' | '<' | '>' => false,
         _ => !chr.is_whitespace(),
     }
 }
 
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_split_name() {
+        assert_eq!(split_name("var:-default"), ("var", ":-default"));
+        assert_eq!(split_name("ns::var#*/"), ("ns::var", "#*/"));
+        assert_eq!(split_name("ns::}"), ("ns", "::}"));
+        assert_eq!(split_name("10%x"), ("10", "%x"));
+        assert_eq!(split_name("?"), ("?", ""));
+    }
+
+    #[test]
+    fn test_split_assignment() {
+        assert_eq!(split_assignment("x=1"), Some(("x", None, false, "1")));
+        assert_eq!(split_assignment("PATH+=:/bin"), Some(("PATH", None, true, ":/bin")));
+        assert_eq!(split_assignment("m[a[1]]=b=c"), Some(("m", Some("a[1]"), false, "b=c")));
+        assert_eq!(split_assignment("ns::m[\"k\"]+=v"), Some(("ns::m", Some("\"k\""), true, "v")));
+        assert_eq!(split_assignment("x+y=1"), None);
+        assert_eq!(split_assignment("m[k=v"), None);
+        assert_eq!(split_assignment("1x=1"), None);
+    }
 
+    #[test]
+    fn test_command_end() {
+        assert_eq!(command_end("echo $(date)) x"), Some(12));
+        assert_eq!(command_end("case x in x) echo a;; (y) echo b; esac) x"), Some(38));
+        assert_eq!(command_end("echo ')' \\) # )\n(cd /)) x"), Some(22));
+        assert_eq!(command_end("echo \"$(echo a\")"), None);
+    }
+}
@@ -13,7 +13,14 @@ mod log;
 mod var;
 //mod eval_alt;
 //mod exec;
-//mod process;
+mod process;
+mod glob;
+mod brace;
+mod expand;
+mod arith;
+mod condition;
+mod diagnostic;
+mod format;
 use lalrpop_util::lalrpop_mod;
 lalrpop_mod!(pub grammar);
 
@@ -24,6 +31,7 @@ use std::error::Error;
 use std::io;
 use std::env;
 use std::fs::File;
+use std::path::Path;
 use std::io::BufReader;
 use std::io::prelude::*;
 
@@ -36,6 +44,10 @@ fn main() {
 
     let args: Vec<String> = env::args().collect();
 
+    if args.get(1).map(String::as_str) == Some("--fmt") {
+        format_scripts(&args[2..]);
+    }
+
     let input = parse_args(args);
 
     /*match read_profile() {
@@ -52,17 +64,19 @@ fn main() {
     shell::set_arg_0();
 
     
-    match read_rc() {
-        Ok(_) => {},
-        Err(_) => {
-            
+    if shell::is_interactive() {
+        match read_rc() {
+            Ok(_) => {},
+            Err(_) => {
+                
+            }
         }
-    }
 
-    match read_user_profile() {
-        Ok(_) => {},
-        Err(_) => {
-            
+        match read_user_profile() {
+            Ok(_) => {},
+            Err(_) => {
+                
+            }
         }
     }
 
@@ -105,7 +119,6 @@ fn parse_args(args: Vec<String>) -> Option<String> {
     let mut pos = 0;
 
     for arg in args.iter() {
-            println!("arg: {}", arg);
         if arg.chars().nth(0).unwrap() == '-' && !non_interactive_mode {
             read_from_args = parse_dash_arg(&arg);
             if read_from_args {
@@ -133,8 +146,6 @@ fn parse_args(args: Vec<String>) -> Option<String> {
 
         args.drain(0..=pos);
 
-        println!("{:?}", args);
-
         let output = args.join(" ");
 
         return Some(output);
@@ -184,7 +195,7 @@ fn interactive_loop() {
     //rl.borrow_mut().bind_sequence(rustyline::Event::KeySeq(vec![rustyline::KeyEvent::ctrl('z')]), rustyline::Cmd::Suspend);
 
     loop {
-        let input;
+        let mut input;
 
         let readline = rl.borrow_mut().readline(shell::expand_var("PS1").unwrap().as_str());
         match readline {
@@ -211,6 +222,27 @@ fn interactive_loop() {
             continue;
         }
 
+        while completion::is_incomplete(&input) {
+            let prompt = shell::expand_var("PS2").unwrap_or_else(|| "> ".to_string());
+            match rl.borrow_mut().readline(prompt.as_str()) {
+                Ok(line) => {
+                    input.push('\n');
+                    input.push_str(&line);
+                },
+                Err(ReadlineError::Interrupted) => {
+                    input.clear();
+                    break;
+                },
+                Err(_) => break,
+            }
+        }
+
+        if input.is_empty() {
+            continue;
+        }
+
+        let _ = rl.borrow_mut().add_history_entry(input.as_str());
+
         /*if input.as_str() == "\n" {
             continue;
         }*/
@@ -220,7 +252,8 @@ fn interactive_loop() {
             .parse(&input,lexer) {
                 Ok(ast) => ast,
                 Err(err) => {
-                    println!("Error: {:?}", err);
+                    eprintln!("{}", diagnostic::parse_error("<stdin>", 1, &input, &err));
+                    eval::set_exit_status(2);
                     continue;
                 }
             };
@@ -246,36 +279,120 @@ fn interactive_loop() {
 }
 
 
-/// This function needs to be changed since it won't work with how the parser works.
-/// This function takes a file name and puts the contents of the file into a buffer.
-/// The buffer is then passed into the parser and evaluated.
+/// This function takes a file name and runs the file as a script.
+/// Lines are read from the file until they make up complete commands, which are then parsed and evaluated,
+/// so a command can span as many lines as the grammar needs. A `#!` line at the start of the file is skipped.
+/// At the end of the file the shell exits with the status of the last command,
+/// or with status 2 if the file has a syntax error or ends in the middle of a command.
+/// A failed `${name:?word}` makes it exit right away with status 1.
 fn script_loop(script_name: &str) {
-    let file = File::open(script_name).unwrap();
+    let file = match File::open(script_name) {
+        Ok(file) => file,
+        Err(err) => {
+            eprintln!("rsh: {}: {}", script_name, err);
+            std::process::exit(127);
+        }
+    };
     let mut buf_reader = BufReader::new(file);
+
+    let mut input = String::new();
+    let mut line_number = 0;
+    // the line of the file that the input starts on
+    let mut first_line = 1;
     loop {
+        let mut line = String::new();
+        let read = match buf_reader.read_line(&mut line) {
+            Ok(read) => read,
+            Err(err) => {
+                eprintln!("rsh: {}: {}", script_name, err);
+                std::process::exit(2);
+            }
+        };
+        line_number += 1;
 
-        let mut input = String::new();
-        buf_reader.read_line(&mut input).unwrap();
+        if line_number == 1 && line.starts_with("#!") {
+            first_line = 2;
+            continue;
+        }
 
+        input.push_str(&line);
+        if read != 0 && completion::is_incomplete(&input) {
+            continue;
+        }
 
         let lexer = Lexer::new(&input);        
-        let mut ast = grammar::CompleteCommandParser::new()
-            .parse(&input,lexer)
-            .unwrap();
+        let mut ast = match grammar::CompleteCommandParser::new()
+            .parse(&input,lexer) {
+                Ok(ast) => ast,
+                Err(err) => {
+                    eprintln!("{}", diagnostic::parse_error(script_name, first_line, &input, &err));
+                    std::process::exit(2);
+                }
+            };
+
+        if let Err(err) = eval::eval(&mut ast) {
+            eprintln!("rsh: {}", err);
+        }
+        if let Some(status) = eval::pending_exit() {
+            std::process::exit(status);
+        }
+
+        if read == 0 {
+            break;
+        }
+        input.clear();
+        first_line = line_number + 1;
+    }
 
-        let _result = eval::eval(&mut ast);
+    std::process::exit(eval::get_exit_code());
+}
 
+/// This function prints the scripts named by the arguments of `--fmt` formatted by format::format,
+/// or the script read from standard input if there are none. The scripts are not run.
+/// The shell exits with status 2 if a script cannot be read or has a syntax error.
+fn format_scripts(names: &[String]) -> ! {
+    let mut status = 0;
+    let names = if names.is_empty() { vec!["-".to_string()] } else { names.to_vec() };
+    for name in &names {
+        let mut input = String::new();
+        let (name, read) = match name.as_str() {
+            "-" => ("<stdin>", io::stdin().read_to_string(&mut input)),
+            name => (name, File::open(name).and_then(|mut file| file.read_to_string(&mut input))),
+        };
+        if let Err(err) = read {
+            eprintln!("rsh: {}: {}", name, err);
+            status = 2;
+            continue;
+        }
+        match format::format(&input) {
+            Ok(formatted) => print!("{}", formatted),
+            Err(err) => {
+                eprintln!("{}", diagnostic::parse_error(name, 1, &input, &err));
+                status = 2;
+            }
+        }
     }
+    std::process::exit(status);
 }
 
 /// This function takes in the commandline arguments as a &str and evaluates it.
 fn read_from_args(input: &str) {
     let lexer = Lexer::new(&input);        
-    let mut ast = grammar::CompleteCommandParser::new()
-        .parse(&input,lexer)
-        .unwrap();
+    let mut ast = match grammar::CompleteCommandParser::new()
+        .parse(&input,lexer) {
+            Ok(ast) => ast,
+            Err(err) => {
+                eprintln!("{}", diagnostic::parse_error("-c", 1, input, &err));
+                std::process::exit(2);
+            }
+        };
 
-    let _result = eval::eval(&mut ast);
+    if let Err(err) = eval::eval(&mut ast) {
+        eprintln!("rsh: {}", err);
+    }
+    if let Some(status) = eval::pending_exit() {
+        std::process::exit(status);
+    }
 }
 
 
@@ -303,7 +420,7 @@ fn read_profile() -> Result<(), Box<dyn Error>>{
         .parse(&system_profile,lexer) {
             Ok(ast) => ast,
             Err(err) => {
-                println!("Error: {:?}", err);
+                eprintln!("{}", diagnostic::parse_error("/etc/profile", 1, &system_profile, &err));
                 return Ok(());
             }
         };
@@ -316,11 +433,14 @@ fn read_profile() -> Result<(), Box<dyn Error>>{
 
 /// This function is similar to read_profile but it instead reads the user profile file located at ~/.profile.
 /// The file is is opened and evaluated as a script.
-/// This function is always called in main in order to load the user profile.
+/// This function is called in main when the shell is interactive in order to load the user profile.
 /// The return value is Ok(()) if the parser is successful.
 /// The return value is Err(err) if the parser fails.
 fn read_user_profile() -> Result<(), Box<dyn Error>> {
-    let file = File::open("~/.profile");
+    let file = match expand::expand_tilde("") {
+        Some(home) => File::open(Path::new(&home).join(".profile")),
+        None => return Ok(()),
+    };
     if file.is_err() {
         return Ok(());
     }
@@ -338,23 +458,28 @@ fn read_user_profile() -> Result<(), Box<dyn Error>> {
         .parse(&user_profile,lexer) {
             Ok(ast) => ast,
             Err(err) => {
-                println!("Error: {:?}", err);
+                eprintln!("{}", diagnostic::parse_error("~/.profile", 1, &user_profile, &err));
                 return Ok(());
             }
         };
 
-    eval::eval(&mut ast).unwrap();
+    if let Err(err) = eval::eval(&mut ast) {
+        eprintln!("rsh: {}", err);
+    }
 
     Ok(())
 }
 
 /// This function reads the user's rshrc file that is located at ~/.rshrc.
 /// The file is is opened and evaluated as a script.
-/// This function is always called in main in order to load the user's rshrc file.
+/// This function is called in main when the shell is interactive in order to load the user's rshrc file.
 /// The return value is Ok(()) if the parser is successful.
 /// The return value is Err(err) if the parser fails.
 fn read_rc() -> Result<(), Box<dyn Error>> {
-    let file = File::open("~/.rshrc");
+    let file = match expand::expand_tilde("") {
+        Some(home) => File::open(Path::new(&home).join(".rshrc")),
+        None => return Ok(()),
+    };
     if file.is_err() {
         return Ok(());
     }
@@ -372,12 +497,14 @@ fn read_rc() -> Result<(), Box<dyn Error>> {
         .parse(&rc,lexer) {
             Ok(ast) => ast,
             Err(err) => {
-                println!("Error: {:?}", err);
+                eprintln!("{}", diagnostic::parse_error("~/.rshrc", 1, &rc, &err));
                 return Ok(());
             }
         };
 
-    eval::eval(&mut ast).unwrap();
+    if let Err(err) = eval::eval(&mut ast) {
+        eprintln!("rsh: {}", err);
+    }
 
     Ok(())
 }
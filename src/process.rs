@@ -1,6 +1,8 @@
 use nix::unistd::Pid;
 
-
+/// This enum describes how a command finished.
+/// Break and Continue are used to unwind the loops that enclose a break or continue builtin.
+#[derive(Debug,Clone,Copy,PartialEq)]
 pub enum CommandExitStatus {
     ExitedWith(i32),
     Running(Pid),
@@ -15,10 +15,12 @@ use rustyline::history::FileHistory;
 use rustyline::config;
 use crate::var::{VarData, VarDataUtils};
 use crate::ast::FunctionBody;
-use crate::context::{ContextManager, Context};
+use crate::context::{ContextManager, Context, Key, Var};
 use crate::completion::CompletionHelper;
+use crate::process::CommandExitStatus;
 
 use std::sync::atomic::AtomicBool;
+use std::os::unix::io::RawFd;
 
 
 /// This is a global variable that is set to true in the child process after a fork.
@@ -88,6 +90,16 @@ pub struct Shell {
     aliases: HashMap<String, String>,
     functions: HashMap<String, FunctionBody>,
     context_manager: ContextManager,
+    // loops
+    /// The number of loops that enclose the command currently being evaluated.
+    loop_depth: usize,
+    /// A pending break or continue and the number of loops it still has to unwind, or a pending return or exit.
+    loop_skip: Option<(CommandExitStatus, usize)>,
+    /// The names of the options turned on with set or shopt.
+    options: HashSet<String>,
+    /// The commands of the process substitutions of the pipeline being expanded,
+    /// with the shell's end of the pipe to each of them.
+    process_substitutions: Vec<(Process, RawFd)>,
 }
 
 /*static DEFAULT_KEYS: Vec<KeyEvent> = vec![
@@ -108,7 +120,7 @@ impl Shell {
         }));*/
         let config = config::Builder::new()
             .behavior(config::Behavior::PreferTerm)
-            .auto_add_history(true)
+            .auto_add_history(false)
             .bell_style(config::BellStyle::Audible)
             .completion_type(config::CompletionType::Fuzzy)
             .build();
@@ -137,6 +149,10 @@ impl Shell {
             aliases: HashMap::new(),
             functions: HashMap::new(),
             context_manager: ContextManager::new(),
+            loop_depth: 0,
+            loop_skip: None,
+            options: HashSet::new(),
+            process_substitutions: Vec::new(),
         }
     } 
 
@@ -238,6 +254,12 @@ impl Shell {
     pub fn push_context_new(&mut self) {
         self.context_manager.push_context_new();
     }
+    pub fn push_function_context(&mut self) {
+        self.context_manager.push_function_context();
+    }
+    pub fn in_function(&self) -> bool {
+        self.context_manager.in_function()
+    }
     /// This function pops the current Context off of the Context stack and returns it.
     pub fn pop_context(&mut self) -> Option<Rc<RefCell<Context>>> {
         self.context_manager.pop_context()
@@ -273,12 +295,16 @@ impl Shell {
         self.context_manager.add_var_pos(set, position);
         //self.var_data.add_var(set, position);
     }
-    /// This function adds a variable to the current context.
+    /// This function adds a variable to the context that has it, or to the global context.
     pub fn add_var_context(&mut self, set: &str) {
         self.context_manager.add_var(set);
         //let pos = self.var_data.get_current_context_pos();
         //self.var_data.add_var(set, pos);
     }
+    /// This function adds a variable to the current context, where it shadows the variables further out.
+    pub fn add_var_local(&mut self, var: Var) {
+        self.context_manager.add_var_local(var);
+    }
     pub fn add_var_readonly(&mut self, set: &str) {
         self.context_manager.add_var_readonly(set);
     }
@@ -303,10 +329,30 @@ impl Shell {
             return None;
         }
         let var = var.unwrap();
-        let x = Some(var.borrow().value.clone());
+        let x = Some(var.borrow().scalar().to_string());
         x
         //self.var_data.lookup_var(var)
     }
+    /// This function returns a copy of a variable, which is how the elements of an array are read.
+    pub fn get_var(&self, name: &str) -> Option<crate::context::Var> {
+        self.context_manager.get_var(name).map(|var| var.borrow().clone())
+    }
+    /// Wrapper to the context manager set array function.
+    pub fn set_array(&mut self, name: &str, values: Vec<String>, append: bool) {
+        self.context_manager.set_array(name, values, append);
+    }
+    /// Wrapper to the context manager set array element function.
+    pub fn set_array_element(&mut self, name: &str, key: &Key, value: &str, append: bool) {
+        self.context_manager.set_array_element(name, key, value, append);
+    }
+    /// Wrapper to the context manager remove array element function.
+    pub fn remove_array_element(&mut self, name: &str, key: &Key) {
+        self.context_manager.remove_array_element(name, key);
+    }
+    /// Wrapper to the context manager declare array function.
+    pub fn declare_array(&mut self, name: &str, associative: bool) {
+        self.context_manager.declare_array(name, associative);
+    }
 
     /// This function takes in a function name and FunctionBody and adds it to the current Context.
     pub fn add_function(&mut self, name: &str, body: FunctionBody) {
@@ -571,6 +617,18 @@ pub fn set_input_args(arg: &str, index: usize) {
     shell.add_var(&set,0);
 }
 
+/// This function gets the positional parameters $1, $2, etc. in order.
+pub fn get_positional_args() -> Vec<String> {
+    let mut shell = SHELL.get().borrow_mut();
+    let mut args = Vec::new();
+    let mut index = 1;
+    while let Some(arg) = shell.expand_variable(&index.to_string()) {
+        args.push(arg);
+        index += 1;
+    }
+    args
+}
+
 /*pub fn push_var_stack() {
     let mut shell = SHELL.get().borrow_mut();
     shell.push_var_stack();
@@ -590,6 +648,16 @@ pub fn push_context_new() {
     let mut shell = SHELL.get().borrow_mut();
     shell.push_context_new();
 }
+/// This function creates a new context for a function that is called and adds it to the context stack.
+pub fn push_function_context() {
+    let mut shell = SHELL.get().borrow_mut();
+    shell.push_function_context();
+}
+/// This function checks if the current context belongs to a running function.
+pub fn in_function() -> bool {
+    let shell = SHELL.get().borrow();
+    shell.in_function()
+}
 /// This function pops a context from the context stack and returns it.
 pub fn pop_context() -> Option<Rc<RefCell<Context>>> {
     let mut shell = SHELL.get().borrow_mut();
@@ -633,16 +701,48 @@ pub fn remove_var(var: &str) {
     let mut shell = SHELL.get().borrow_mut();
     shell.remove_var(var);
 }
-/// This function adds a variable to the shell at the current context.
+/// This function adds a variable to the shell at the context that has it,
+/// or at the global context if it does not exist yet.
 pub fn add_var_context(set: &str) {
     let mut shell = SHELL.get().borrow_mut();
     shell.add_var_context(set);
 }
+/// This function adds a variable to the current context unless it is already there,
+/// which makes it local to the function that is running.
+pub fn add_var_local(var: Var) {
+    let mut shell = SHELL.get().borrow_mut();
+    shell.add_var_local(var);
+}
 /// This function takes in a variable name and returns the value of that variable if it exists.
 pub fn expand_var(var: &str) -> Option<String> {
     let mut shell = SHELL.get().borrow_mut();
     shell.expand_variable(var)
 }
+/// This function takes in a variable name and returns a copy of the variable if it exists.
+pub fn get_var(name: &str) -> Option<crate::context::Var> {
+    let shell = SHELL.get().borrow();
+    shell.get_var(name)
+}
+/// This function sets a variable to an indexed array, or appends the values to the array if `append` is set.
+pub fn set_array(name: &str, values: Vec<String>, append: bool) {
+    let mut shell = SHELL.get().borrow_mut();
+    shell.set_array(name, values, append);
+}
+/// This function sets an element of an array, or appends to the element if `append` is set.
+pub fn set_array_element(name: &str, key: &Key, value: &str, append: bool) {
+    let mut shell = SHELL.get().borrow_mut();
+    shell.set_array_element(name, key, value, append);
+}
+/// This function removes an element of an array.
+pub fn remove_array_element(name: &str, key: &Key) {
+    let mut shell = SHELL.get().borrow_mut();
+    shell.remove_array_element(name, key);
+}
+/// This function makes a variable an indexed or an associative array.
+pub fn declare_array(name: &str, associative: bool) {
+    let mut shell = SHELL.get().borrow_mut();
+    shell.declare_array(name, associative);
+}
 pub fn print_readonly_vars() {
     let shell = SHELL.get().borrow();
     shell.all_readonly_vars().iter().for_each(|var| {
@@ -690,3 +790,67 @@ pub fn print_readonly_functions() {
         println!("{}", name);
     }
 }
+
+/// This function is called when the evaluation of a loop begins.
+pub fn enter_loop() {
+    let mut shell = SHELL.get().borrow_mut();
+    shell.loop_depth += 1;
+}
+/// This function is called when the evaluation of a loop ends.
+pub fn exit_loop() {
+    let mut shell = SHELL.get().borrow_mut();
+    shell.loop_depth -= 1;
+}
+/// This function gets the number of loops that enclose the current command.
+pub fn get_loop_depth() -> usize {
+    let shell = SHELL.get().borrow();
+    shell.loop_depth
+}
+/// This function records a break or continue that has to unwind `count` enclosing loops, or a return or an exit.
+pub fn set_loop_skip(skip: CommandExitStatus, count: usize) {
+    let mut shell = SHELL.get().borrow_mut();
+    shell.loop_skip = Some((skip, count));
+}
+/// This function gets the pending break, continue, return or exit if there is one.
+pub fn get_loop_skip() -> Option<(CommandExitStatus, usize)> {
+    let shell = SHELL.get().borrow();
+    shell.loop_skip
+}
+/// This function clears the pending break, continue, return or exit.
+pub fn clear_loop_skip() {
+    let mut shell = SHELL.get().borrow_mut();
+    shell.loop_skip = None;
+}
+
+/// This function turns a shell option such as noglob on or off.
+pub fn set_option(name: &str, on: bool) {
+    let mut shell = SHELL.get().borrow_mut();
+    if on {
+        shell.options.insert(name.to_string());
+    }
+    else {
+        shell.options.remove(name);
+    }
+}
+/// This function checks if a shell option is turned on.
+pub fn get_option(name: &str) -> bool {
+    let shell = SHELL.get().borrow();
+    shell.options.contains(name)
+}
+
+/// This function records the command of a process substitution and the shell's end of the pipe to it.
+pub fn add_process_substitution(process: Process, fd: RawFd) {
+    let mut shell = SHELL.get().borrow_mut();
+    shell.process_substitutions.push((process, fd));
+}
+/// This function takes the process substitutions recorded so far, leaving none behind.
+pub fn take_process_substitutions() -> Vec<(Process, RawFd)> {
+    let mut shell = SHELL.get().borrow_mut();
+    std::mem::take(&mut shell.process_substitutions)
+}
+/// This function replaces the recorded process substitutions, which is how a pipeline puts back the ones
+/// of the pipeline that it is nested in.
+pub fn set_process_substitutions(process_substitutions: Vec<(Process, RawFd)>) {
+    let mut shell = SHELL.get().borrow_mut();
+    shell.process_substitutions = process_substitutions;
+}
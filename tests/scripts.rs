@@ -0,0 +1,57 @@
+//! These tests run scripts with the rsh binary and check what they print and the status that they exit with.
+use std::process::Command;
+
+/// This function runs a script with `rsh -c` and gives its standard output and exit status.
+fn run(script: &str) -> (String, i32) {
+    let output = Command::new(env!("CARGO_BIN_EXE_rsh")).arg("-c").arg(script).output().unwrap();
+    (String::from_utf8_lossy(&output.stdout).into_owned(), output.status.code().unwrap_or(-1))
+}
+
+/// This function runs a script from a file with rsh and gives its standard output and exit status.
+fn run_file(name: &str, script: &str) -> (String, i32) {
+    let path = std::env::temp_dir().join(format!("rsh-test-{}-{}.sh", std::process::id(), name));
+    std::fs::write(&path, script).unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_rsh")).arg(&path).output().unwrap();
+    let _ = std::fs::remove_file(&path);
+    (String::from_utf8_lossy(&output.stdout).into_owned(), output.status.code().unwrap_or(-1))
+}
+
+#[test]
+fn test_return() {
+    assert_eq!(run("f() { while true; do return 3; done; }; f; echo $?"), ("3\n".to_string(), 0));
+    assert_eq!(
+        run("f() { for i in 1 2 3; do if [ $i = 2 ]; then return 5; fi; echo $i; done; echo no; }; f; echo $?"),
+        ("1\n5\n".to_string(), 0)
+    );
+    assert_eq!(run("f() { echo a; return; echo b; }; f; echo $?"), ("a\n0\n".to_string(), 0));
+    assert_eq!(run("f() { false; return; }; f; echo $?"), ("1\n".to_string(), 0));
+    assert_eq!(run("f() { for i in 1; do for j in 1; do return 7; done; done; }; for k in 1 2; do f; echo $k $?; done"), ("1 7\n2 7\n".to_string(), 0));
+}
+
+#[test]
+fn test_parameter_error() {
+    assert_eq!(run("echo ${nope:?unset}; echo no"), (String::new(), 1));
+    assert_eq!(run("f() { echo ${nope:?}; echo no; }; f; echo no"), (String::new(), 1));
+    assert_eq!(run("for i in 1 2; do echo $i ${nope:?}; done; echo no"), (String::new(), 1));
+    assert_eq!(run("echo ${HOME:?} >/dev/null; echo yes"), ("yes\n".to_string(), 0));
+}
+
+#[test]
+fn test_line_continuation() {
+    assert_eq!(run_file("continuation", "echo long \\\n  arg\necho x\\\ny\necho \"q\\\nr\"\necho end \\\\\n"), ("long arg\nxy\nqr\nend \\\n".to_string(), 0));
+}
+
+#[test]
+fn test_function_pipeline() {
+    assert_eq!(run("f() { echo abc; }; f | tr a-z A-Z; f | cat >/dev/null; echo ${PIPESTATUS[@]}"), ("ABC\n0 0\n".to_string(), 0));
+    assert_eq!(run("f() { return 4; }; true | f; echo ${PIPESTATUS[@]} $?; set -o pipefail; f | true; echo $?"), ("0 4 4\n4\n".to_string(), 0));
+}
+
+#[test]
+fn test_builtin_redirect() {
+    let path = std::env::temp_dir().join(format!("rsh-test-{}-redirect", std::process::id()));
+    let path = path.display();
+    assert_eq!(run("x=a; [ \"$x\" -eq 1 ] 2>/dev/null; echo $?"), ("2\n".to_string(), 0));
+    assert_eq!(run(&format!("pwd >{}; test -f {} && echo yes; cd / >/dev/null; echo $?", path, path)), ("yes\n0\n".to_string(), 0));
+    let _ = std::fs::remove_file(path.to_string());
+}